@@ -0,0 +1,214 @@
+// Compares the whole-buffer search with the line by line search it replaced, and with the
+// 'Searcher' that 'run' uses, which splits lines but skips those before the next match. Run with
+//
+//   cargo bench --bench search
+//
+// Every case is run a few times and the fastest run is reported, which is the least disturbed by
+// whatever else the machine is doing
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use minigrep::search::{CaseInsensitive, Literal};
+use minigrep::{search_case_insensitive, search_case_sensitive, Line, Matcher, Searcher};
+
+const RUNS: usize = 10;
+
+// The implementations from before the whole-buffer search, kept to compare against
+fn naive_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'a str)> {
+    let mut results: Vec<(u32, &'a str)> = Vec::new();
+    let mut line_number: u32 = 0;
+
+    for line in contents.lines() {
+        line_number += 1;
+        if line.contains(query) {
+            results.push((line_number, line));
+        }
+    }
+
+    results
+}
+
+fn naive_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'a str)> {
+    let mut results: Vec<(u32, &'a str)> = Vec::new();
+    let mut line_number: u32 = 0;
+
+    let query = query.to_lowercase();
+
+    for line in contents.lines() {
+        line_number += 1;
+        if line.to_lowercase().contains(&query) {
+            results.push((line_number, line));
+        }
+    }
+
+    results
+}
+
+// Counts the selected lines the way 'run' finds them
+fn searcher(matcher: &impl Matcher, contents: &str) -> usize {
+    let mut lines = 0;
+    Searcher::default()
+        .search_reader(matcher, contents.as_bytes(), &mut |_: &Line<'_>| {
+            lines += 1;
+            Ok(())
+        })
+        .unwrap();
+    lines
+}
+
+fn searcher_case_sensitive(query: &str, contents: &str) -> usize {
+    searcher(&Literal::new(query), contents)
+}
+
+fn searcher_case_insensitive(query: &str, contents: &str) -> usize {
+    searcher(&CaseInsensitive::new(query), contents)
+}
+
+type Search = for<'a> fn(&str, &'a str) -> Vec<(u32, &'a str)>;
+type Count = fn(&str, &str) -> usize;
+
+fn fastest(search: impl Fn(&str, &str) -> usize, query: &str, contents: &str) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut lines = 0;
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        lines = black_box(search(black_box(query), black_box(contents)));
+        best = best.min(start.elapsed());
+    }
+
+    (best, lines)
+}
+
+fn bench(name: &str, query: &str, contents: &str, naive: Search, fast: Search, searcher: Count) {
+    let (naive_time, naive_lines) = fastest(|q, c| naive(q, c).len(), query, contents);
+    let (fast_time, fast_lines) = fastest(|q, c| fast(q, c).len(), query, contents);
+    let (searcher_time, searcher_lines) = fastest(searcher, query, contents);
+    assert_eq!(naive_lines, fast_lines, "{name}: the searches disagree");
+    assert_eq!(
+        naive_lines, searcher_lines,
+        "{name}: the searcher disagrees"
+    );
+
+    let megabytes = contents.len() as f64 / 1_000_000.0;
+    println!(
+        "{name:<32} {naive_lines:>7} lines   naive {:>8.2} MB/s   whole buffer {:>8.2} MB/s   {:>5.1}x   searcher {:>8.2} MB/s   {:>5.1}x",
+        megabytes / naive_time.as_secs_f64(),
+        megabytes / fast_time.as_secs_f64(),
+        naive_time.as_secs_f64() / fast_time.as_secs_f64(),
+        megabytes / searcher_time.as_secs_f64(),
+        naive_time.as_secs_f64() / searcher_time.as_secs_f64()
+    );
+}
+
+// About 40 MB of text in lines of varying length, with a few rare words sprinkled in
+fn corpus() -> String {
+    let words = [
+        "the",
+        "quick",
+        "brown",
+        "fox",
+        "jumps",
+        "over",
+        "lazy",
+        "dog",
+        "minigrep",
+        "search",
+        "buffer",
+        "line",
+        "Rust",
+        "ownership",
+        "borrow",
+        "lifetime",
+        "trait",
+        "closure",
+    ];
+    let mut contents = String::with_capacity(40_000_000);
+    // A small linear congruential generator keeps the corpus the same on every run
+    let mut state: u32 = 1;
+    let mut next = move || {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as usize
+    };
+
+    while contents.len() < 40_000_000 {
+        for _ in 0..next() % 16 + 1 {
+            contents.push_str(words[next() % words.len()]);
+            contents.push(' ');
+        }
+        if next() % 10_000 == 0 {
+            contents.push_str("needle ");
+        }
+        contents.push('\n');
+    }
+
+    contents
+}
+
+fn main() {
+    let contents = corpus();
+    let poem = include_str!("../poem.txt").repeat(200_000);
+
+    let sensitive: Search = search_case_sensitive;
+    let insensitive: Search = search_case_insensitive;
+    let searcher_sensitive: Count = searcher_case_sensitive;
+    let searcher_insensitive: Count = searcher_case_insensitive;
+
+    bench(
+        "rare word",
+        "needle",
+        &contents,
+        naive_case_sensitive,
+        sensitive,
+        searcher_sensitive,
+    );
+    bench(
+        "common word",
+        "fox",
+        &contents,
+        naive_case_sensitive,
+        sensitive,
+        searcher_sensitive,
+    );
+    bench(
+        "single byte",
+        "z",
+        &contents,
+        naive_case_sensitive,
+        sensitive,
+        searcher_sensitive,
+    );
+    bench(
+        "missing word",
+        "haystack",
+        &contents,
+        naive_case_sensitive,
+        sensitive,
+        searcher_sensitive,
+    );
+    bench(
+        "poem",
+        "frog",
+        &poem,
+        naive_case_sensitive,
+        sensitive,
+        searcher_sensitive,
+    );
+    bench(
+        "rare word ignoring case",
+        "NEEDLE",
+        &contents,
+        naive_case_insensitive,
+        insensitive,
+        searcher_insensitive,
+    );
+    bench(
+        "poem ignoring case",
+        "BOG",
+        &poem,
+        naive_case_insensitive,
+        insensitive,
+        searcher_insensitive,
+    );
+}
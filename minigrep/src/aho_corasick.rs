@@ -0,0 +1,213 @@
+use crate::case::fold;
+
+// Finds many literal patterns in a single pass over the text with the Aho–Corasick algorithm.
+// The patterns are stored in a trie whose nodes also link to the longest proper suffix that is a
+// node too, so when the next character doesn't continue the current prefix the search falls back
+// to that suffix instead of starting over. The time per line is linear in its length no matter
+// how many patterns there are.
+//
+// The trie is built over characters so case-insensitive searches can fold both the patterns and
+// the text with 'case::fold', which never changes the number of characters
+
+// Marks a missing link
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Node {
+    // Children sorted by character
+    next: Vec<(char, usize)>,
+    // Longest proper suffix of this node that is also a node
+    fail: usize,
+    // Longest proper suffix of this node that ends a pattern, or NONE
+    output: usize,
+    // Index of the pattern ending at this node, the first one when patterns repeat
+    pattern: Option<usize>,
+    // Number of characters from the root
+    depth: usize,
+}
+
+impl Node {
+    fn new(depth: usize) -> Node {
+        Node {
+            next: Vec::new(),
+            fail: 0,
+            output: NONE,
+            pattern: None,
+            depth,
+        }
+    }
+
+    fn child(&self, c: char) -> Option<usize> {
+        self.next
+            .binary_search_by_key(&c, |&(c, _)| c)
+            .ok()
+            .map(|i| self.next[i].1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    ignore_case: bool,
+    // The first empty pattern, which matches every line
+    empty: Option<usize>,
+}
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S], ignore_case: bool) -> AhoCorasick {
+        let mut nodes = vec![Node::new(0)];
+        let mut empty = None;
+
+        for (index, pattern) in patterns.iter().enumerate() {
+            let mut node = 0;
+            for c in pattern.as_ref().chars() {
+                let c = if ignore_case { fold(c) } else { c };
+                node = match nodes[node].next.binary_search_by_key(&c, |&(c, _)| c) {
+                    Ok(i) => nodes[node].next[i].1,
+                    Err(i) => {
+                        let child = nodes.len();
+                        nodes.push(Node::new(nodes[node].depth + 1));
+                        nodes[node].next.insert(i, (c, child));
+                        child
+                    }
+                };
+            }
+
+            if node == 0 {
+                empty = empty.or(Some(index));
+            } else if nodes[node].pattern.is_none() {
+                nodes[node].pattern = Some(index);
+            }
+        }
+
+        // Suffix links are computed breadth first, so the links of every shorter node are known
+        let mut queue = std::collections::VecDeque::from([0]);
+        while let Some(node) = queue.pop_front() {
+            for i in 0..nodes[node].next.len() {
+                let (c, child) = nodes[node].next[i];
+                queue.push_back(child);
+
+                let fail = match node {
+                    0 => 0,
+                    _ => {
+                        let mut fail = nodes[node].fail;
+                        loop {
+                            if let Some(next) = nodes[fail].child(c) {
+                                break next;
+                            }
+                            if fail == 0 {
+                                break 0;
+                            }
+                            fail = nodes[fail].fail;
+                        }
+                    }
+                };
+                nodes[child].fail = fail;
+                nodes[child].output = match nodes[fail].pattern {
+                    Some(_) => fail,
+                    None => nodes[fail].output,
+                };
+            }
+        }
+
+        AhoCorasick {
+            nodes,
+            ignore_case,
+            empty,
+        }
+    }
+
+    // Byte offsets of the leftmost-longest non-overlapping matches in 'text', each with the index
+    // of the pattern that matched
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize, usize)> {
+        // 'longest[i]' is the end and the pattern of the longest match starting at character 'i'
+        let mut longest: Vec<Option<(usize, usize)>> = Vec::new();
+        // Byte offset of every character seen so far
+        let mut offsets = Vec::new();
+        let mut node = 0;
+
+        for (i, c) in text.char_indices() {
+            offsets.push(i);
+            let c = if self.ignore_case { fold(c) } else { c };
+
+            node = loop {
+                if let Some(next) = self.nodes[node].child(c) {
+                    break next;
+                }
+                if node == 0 {
+                    break 0;
+                }
+                node = self.nodes[node].fail;
+            };
+
+            let mut found = match self.nodes[node].pattern {
+                Some(_) => node,
+                None => self.nodes[node].output,
+            };
+            while found != NONE {
+                let start = offsets.len() - self.nodes[found].depth;
+                let end = i + c.len_utf8();
+                let pattern = self.nodes[found].pattern.unwrap_or(0);
+
+                if longest.len() <= start {
+                    longest.resize(start + 1, None);
+                }
+                // Later matches ending here are shorter, so only the first one per start counts
+                if longest[start].is_none_or(|(longest_end, _)| longest_end < end) {
+                    longest[start] = Some((end, pattern));
+                }
+                found = self.nodes[found].output;
+            }
+        }
+
+        let mut matches = Vec::new();
+        let mut next = 0;
+        for (start, found) in longest.into_iter().enumerate() {
+            if let Some((end, pattern)) = found {
+                let start = offsets[start];
+                if start >= next {
+                    matches.push((start, end, pattern));
+                    next = end;
+                }
+            }
+        }
+
+        if matches.is_empty() {
+            if let Some(pattern) = self.empty {
+                matches.push((0, 0, pattern));
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftmost_longest_matches() {
+        let patterns = ["bc", "abcd", "cde", "he", "she", "hers"];
+        let ac = AhoCorasick::new(&patterns, false);
+
+        assert_eq!(vec![(0, 4, 1)], ac.find_all("abcde"));
+        assert_eq!(vec![(1, 3, 0), (3, 6, 2)], ac.find_all("xbccde"));
+        assert_eq!(vec![(0, 3, 4), (4, 8, 5)], ac.find_all("she hers"));
+        assert!(ac.find_all("nothing").is_empty());
+    }
+
+    #[test]
+    fn case_insensitive_and_repeated_patterns() {
+        let ac = AhoCorasick::new(&["straße", "STRASSE", "Straße"], true);
+
+        assert_eq!(vec![(0, 8, 0), (9, 16, 1)], ac.find_all("STRAẞE strasse"));
+    }
+
+    #[test]
+    fn empty_pattern_matches_every_line() {
+        let ac = AhoCorasick::new(&["frog", ""], false);
+
+        assert_eq!(vec![(0, 0, 1)], ac.find_all("toad"));
+        assert_eq!(vec![(2, 6, 0)], ac.find_all("a frog"));
+    }
+}
@@ -0,0 +1,976 @@
+use std::env;
+use std::error;
+use std::fmt;
+
+use crate::config_file;
+use crate::encoding::Encoding;
+use crate::{ColorChoice, Config, Error, MmapChoice, OutputMode};
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [FILE]...
+       minigrep [OPTIONS] -e QUERY... [-f FILE]... [FILE]...
+       minigrep index [OPTIONS] [DIR]...";
+
+pub const HELP: &str = "\
+Usage: minigrep [OPTIONS] QUERY [FILE]...
+       minigrep [OPTIONS] -e QUERY... [-f FILE]... [FILE]...
+       minigrep index [OPTIONS] [DIR]...
+
+Search for QUERY in each FILE. Directories are searched recursively. When FILE is '-' or no FILE
+is given, standard input is searched.
+
+'minigrep index' builds or updates a trigram index of each DIR, the current directory by default,
+in a file named .minigrep-index. Searching an indexed directory for a plain QUERY skips the files
+that can't contain it. Files changed since the index was built are always searched, run
+'minigrep index' again to update it. The walking options below apply to the index as well.
+
+Options:
+  -e, --pattern QUERY        Search for QUERY. Can be given several times to select the lines
+                             matching any of them
+  -f, --file FILE            Search for every line of FILE as a separate QUERY
+  -i, --ignore-case          Ignore case distinctions (or set IGNORE_CASE)
+  -S, --smart-case           Ignore case distinctions unless QUERY has an uppercase letter
+  -s, --case-sensitive       Don't ignore case distinctions, undoing -i and -S
+  -E, --regex                Treat QUERY as a regular expression (or set REGEX)
+  -F, --fixed-strings        Treat QUERY as plain text, undoing -E
+      --fuzzy K              Also match substrings within K insertions, deletions or
+                             substitutions of QUERY
+  -w, --word-regexp          Only match whole words
+  -U, --multiline            Let matches span lines, printing every line a match touches as
+                             one block. In a plain QUERY, '\\n' stands for a line break
+  -x, --line-regexp          Only match whole lines
+  -n, --line-number          Prefix each line with its line number
+  -v, --invert-match         Select lines that don't match
+  -c, --count                Print only the number of selected lines per file
+  -l, --files-with-matches   Print only the names of files with selected lines
+  -L, --files-without-match  Print only the names of files without selected lines
+      --json                 Print results as JSON Lines, one object per event
+  -a, --binary               Search binary files as text instead of only printing whether
+                             they match
+  -z, --decompress           Search inside gzip, bzip2, xz and zstd compressed files
+      --encoding NAME        Read files as 'utf-8', 'utf-16le', 'utf-16be', 'latin1' or
+                             'windows-1252' instead of by their byte order mark or as UTF-8
+  -r, --replace TEXT         Preview replacing every match with TEXT as a diff. With -E, TEXT
+                             can refer to capture groups as $1 or ${1}, and to the whole
+                             match as $0. Use $$ for a literal $
+      --in-place             Write the replacements back to the files instead of previewing
+  -m, --max-count NUM        Stop searching a file after NUM selected lines
+  -A, --after-context NUM    Print NUM lines of context after each match
+  -B, --before-context NUM   Print NUM lines of context before each match
+  -C, --context NUM          Print NUM lines of context around each match
+      --include GLOB         Only search files matching GLOB
+      --exclude GLOB         Skip files and directories matching GLOB
+  -t, --type TYPE            Only search files of TYPE, like 'rust' or 'py'
+  -T, --type-not TYPE        Skip files of TYPE
+      --type-add TYPE:GLOB   Add GLOB to the files of TYPE, defining TYPE if it's new. Several
+                             globs can be separated with commas
+      --follow               Follow symbolic links while walking directories
+      --no-ignore            Don't skip paths ignored by .gitignore, .ignore and
+                             .git/info/exclude files while walking directories
+      --hidden               Search hidden files and directories while walking directories
+  -j, --threads NUM          Search NUM files at once, one per CPU core by default
+      --ordered              Print the results of every file in the order the files were
+                             given, even when searching in parallel
+      --mmap                 Memory-map every file instead of only large ones
+      --no-mmap              Never memory-map files, always read them
+      --color WHEN           Highlight matches: 'auto' (the default), 'always' or 'never'.
+                             'auto' only colors terminals and respects NO_COLOR
+      --no-config            Don't read the configuration file
+      --help                 Print this help and exit
+  -V, --version              Print the version and exit
+
+Use '--' to mark the end of the options, e.g. 'minigrep -- -v file.txt'.
+
+Default options are read from the file named by MINIGREP_CONFIG, or else from minigrep/config in
+the XDG config directory ($XDG_CONFIG_HOME, ~/.config by default). It holds one argument per
+line, and lines starting with '#' are comments. Options on the command line win over the ones in
+the file, which win over IGNORE_CASE and REGEX.
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgsError {
+    pub message: String,
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for ArgsError {}
+
+fn error(message: String) -> ArgsError {
+    ArgsError { message }
+}
+
+// Only one is ever created, so the size of 'Config' doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum Command {
+    Search(Config),
+    // Build or update the index of 'paths', see the 'index' module
+    Index(Config),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opt {
+    Pattern,
+    File,
+    IgnoreCase,
+    SmartCase,
+    CaseSensitive,
+    Regex,
+    FixedStrings,
+    Fuzzy,
+    WordRegexp,
+    LineRegexp,
+    Multiline,
+    LineNumber,
+    InvertMatch,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    Json,
+    Binary,
+    Decompress,
+    Encoding,
+    Replace,
+    InPlace,
+    MaxCount,
+    AfterContext,
+    BeforeContext,
+    Context,
+    Include,
+    Exclude,
+    Type,
+    TypeNot,
+    TypeAdd,
+    Follow,
+    NoIgnore,
+    Hidden,
+    Threads,
+    Ordered,
+    Mmap,
+    NoMmap,
+    Color,
+    NoConfig,
+    Help,
+    Version,
+}
+
+// File types for '--type' and '--type-not', more can be defined with '--type-add'
+const TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    ("cpp", &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.h"]),
+    ("go", &["*.go"]),
+    ("js", &["*.js", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+// (option, short name, long name, takes a value)
+const OPTIONS: &[(Opt, Option<char>, &str, bool)] = &[
+    (Opt::Pattern, Some('e'), "pattern", true),
+    (Opt::File, Some('f'), "file", true),
+    (Opt::IgnoreCase, Some('i'), "ignore-case", false),
+    (Opt::SmartCase, Some('S'), "smart-case", false),
+    (Opt::CaseSensitive, Some('s'), "case-sensitive", false),
+    (Opt::Regex, Some('E'), "regex", false),
+    (Opt::FixedStrings, Some('F'), "fixed-strings", false),
+    (Opt::Fuzzy, None, "fuzzy", true),
+    (Opt::WordRegexp, Some('w'), "word-regexp", false),
+    (Opt::LineRegexp, Some('x'), "line-regexp", false),
+    (Opt::Multiline, Some('U'), "multiline", false),
+    (Opt::LineNumber, Some('n'), "line-number", false),
+    (Opt::InvertMatch, Some('v'), "invert-match", false),
+    (Opt::Count, Some('c'), "count", false),
+    (
+        Opt::FilesWithMatches,
+        Some('l'),
+        "files-with-matches",
+        false,
+    ),
+    (
+        Opt::FilesWithoutMatch,
+        Some('L'),
+        "files-without-match",
+        false,
+    ),
+    (Opt::Json, None, "json", false),
+    (Opt::Binary, Some('a'), "binary", false),
+    (Opt::Decompress, Some('z'), "decompress", false),
+    (Opt::Encoding, None, "encoding", true),
+    (Opt::Replace, Some('r'), "replace", true),
+    (Opt::InPlace, None, "in-place", false),
+    (Opt::MaxCount, Some('m'), "max-count", true),
+    (Opt::AfterContext, Some('A'), "after-context", true),
+    (Opt::BeforeContext, Some('B'), "before-context", true),
+    (Opt::Context, Some('C'), "context", true),
+    (Opt::Include, None, "include", true),
+    (Opt::Exclude, None, "exclude", true),
+    (Opt::Type, Some('t'), "type", true),
+    (Opt::TypeNot, Some('T'), "type-not", true),
+    (Opt::TypeAdd, None, "type-add", true),
+    (Opt::Follow, None, "follow", false),
+    (Opt::NoIgnore, None, "no-ignore", false),
+    (Opt::Hidden, None, "hidden", false),
+    (Opt::Threads, Some('j'), "threads", true),
+    (Opt::Ordered, None, "ordered", false),
+    (Opt::Mmap, None, "mmap", false),
+    (Opt::NoMmap, None, "no-mmap", false),
+    (Opt::Color, None, "color", true),
+    (Opt::NoConfig, None, "no-config", false),
+    (Opt::Help, None, "help", false),
+    (Opt::Version, Some('V'), "version", false),
+];
+
+impl Command {
+    // 'args[0]' is the name of the binary. The options in the configuration file come before the
+    // ones on the command line, see the 'config_file' module. Errors are always 'Error::Args'
+    pub fn parse(args: &[String]) -> Result<Command, Error> {
+        Command::parse_with_env(args, config_file::load, |name| env::var_os(name).is_some())
+            .map_err(Error::Args)
+    }
+
+    // 'load' returns the path of the configuration file and the arguments in it
+    fn parse_with_env(
+        args: &[String],
+        load: impl FnOnce() -> Result<Option<(String, Vec<String>)>, ArgsError>,
+        env_is_set: impl Fn(&str) -> bool,
+    ) -> Result<Command, ArgsError> {
+        let mut config = Config {
+            ignore_case: env_is_set("IGNORE_CASE"),
+            regex: env_is_set("REGEX"),
+            ..Config::default()
+        };
+        let mut types = Types::default();
+        let mut operands = Vec::new();
+
+        // Searching for the word 'index' needs '-e index' or '-- index'
+        let index = args.get(1).is_some_and(|arg| arg == "index");
+        let args = &args[args.len().min(1 + index as usize)..];
+
+        // Whether to read the file is only known once the command line was parsed, so it's
+        // parsed once on its own first. '--help' then works even with a broken file
+        let mut given = Config::default();
+        let parsed = parse_args(args, &mut given, &mut Types::default(), &mut Vec::new())?;
+        if let Some(command) = parsed {
+            return Ok(command);
+        }
+        let defaults = match given.no_config {
+            true => None,
+            false => load()?,
+        };
+
+        if let Some((path, defaults)) = defaults {
+            let in_file = |err: ArgsError| error(format!("in config file '{path}': {err}"));
+            // '--help' or '--version' in the file would stop every search
+            let parsed = parse_args(&defaults, &mut config, &mut types, &mut operands);
+            if parsed.map_err(in_file)?.is_some() {
+                return Err(in_file(error(
+                    "options '--help' and '--version' can only be given on the command line"
+                        .to_string(),
+                )));
+            }
+            if let Some(operand) = operands.first() {
+                return Err(in_file(error(format!(
+                    "only options can be given, found '{operand}'"
+                ))));
+            }
+            // The query would turn into the first file to search
+            if !config.patterns.is_empty() || !config.pattern_files.is_empty() {
+                return Err(in_file(error(
+                    "options '--pattern' and '--file' can only be given on the command line"
+                        .to_string(),
+                )));
+            }
+        }
+
+        if let Some(command) = parse_args(args, &mut config, &mut types, &mut operands)? {
+            return Ok(command);
+        }
+        types.resolve(&mut config)?;
+
+        if index {
+            config.paths = operands;
+            if config.paths.is_empty() {
+                config.paths.push(".".to_string());
+            }
+            return Ok(Command::Index(config));
+        }
+
+        let mut operands = operands.into_iter();
+        // Without '-e' or '-f' the first operand is the query
+        if config.patterns.is_empty() && config.pattern_files.is_empty() {
+            let query = operands
+                .next()
+                .ok_or_else(|| error("missing search query".to_string()))?;
+            config.patterns.push(query);
+        }
+        config.paths = operands.collect();
+        // Without any file operand standard input is searched
+        if config.paths.is_empty() {
+            config.paths.push("-".to_string());
+        }
+
+        if config.in_place && config.replace.is_none() {
+            return Err(error(
+                "option '--in-place' requires '--replace'".to_string(),
+            ));
+        }
+        // Files are written back as UTF-8, which would change the encoding of the rest of them
+        if config.in_place && config.encoding.is_some() {
+            return Err(error(
+                "option '--in-place' can't be combined with '--encoding'".to_string(),
+            ));
+        }
+        // Every file is rewritten whole, so there are no lines to invert, count, list or stop at
+        let ignored = [
+            (config.invert_match, "invert-match"),
+            (config.output == OutputMode::Count, "count"),
+            (
+                config.output == OutputMode::FilesWithMatches,
+                "files-with-matches",
+            ),
+            (
+                config.output == OutputMode::FilesWithoutMatch,
+                "files-without-match",
+            ),
+            (config.max_count.is_some(), "max-count"),
+        ];
+        if let Some((_, name)) = ignored.iter().find(|(set, _)| config.in_place && *set) {
+            return Err(error(format!(
+                "option '--in-place' can't be combined with '--{name}'"
+            )));
+        }
+        if config.in_place && config.paths.iter().any(|path| path == "-") {
+            return Err(error(
+                "option '--in-place' can't edit standard input".to_string(),
+            ));
+        }
+
+        // Replacements are made line by line, so they can't follow a match onto the next line
+        if config.multiline && config.replace.is_some() {
+            return Err(error(
+                "option '--replace' can't be combined with '--multiline'".to_string(),
+            ));
+        }
+
+        // Approximate matching only works on plain queries and finds its own boundaries
+        if config.fuzzy.is_some() {
+            let conflict = [
+                (config.regex, "--regex"),
+                (config.word_regexp, "--word-regexp"),
+                (config.line_regexp, "--line-regexp"),
+            ]
+            .into_iter()
+            .find_map(|(set, name)| set.then_some(name));
+            if let Some(name) = conflict {
+                return Err(error(format!(
+                    "option '--fuzzy' can't be combined with '{name}'"
+                )));
+            }
+        }
+
+        Ok(Command::Search(config))
+    }
+}
+
+// Applies the options in 'args' in order and collects the operands. Returns a command when an
+// option ends parsing early
+fn parse_args(
+    args: &[String],
+    config: &mut Config,
+    types: &mut Types,
+    operands: &mut Vec<String>,
+) -> Result<Option<Command>, ArgsError> {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args.by_ref().cloned());
+            break;
+        }
+
+        // A lone '-' is an operand, not an option
+        if !arg.starts_with('-') || arg == "-" {
+            operands.push(arg.clone());
+            continue;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let &(opt, _, _, takes_value) = OPTIONS
+                .iter()
+                .find(|option| option.2 == name)
+                .ok_or_else(|| error(format!("unknown option '--{name}'")))?;
+
+            let value = match (takes_value, inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(
+                    args.next()
+                        .cloned()
+                        .ok_or_else(|| error(format!("option '--{name}' requires a value")))?,
+                ),
+                (false, Some(_)) => {
+                    return Err(error(format!("option '--{name}' doesn't take a value")))
+                }
+                (false, None) => None,
+            };
+
+            match apply(config, types, opt, &format!("--{name}"), value)? {
+                Some(command) => return Ok(Some(command)),
+                None => continue,
+            }
+        }
+
+        // A cluster of short options such as '-in' or '-C2'
+        let cluster = &arg[1..];
+        for (i, c) in cluster.char_indices() {
+            let &(opt, _, _, takes_value) = OPTIONS
+                .iter()
+                .find(|option| option.1 == Some(c))
+                .ok_or_else(|| error(format!("unknown option '-{c}'")))?;
+
+            let value = if takes_value {
+                let rest = &cluster[i + c.len_utf8()..];
+                match rest {
+                    "" => Some(
+                        args.next()
+                            .cloned()
+                            .ok_or_else(|| error(format!("option '-{c}' requires a value")))?,
+                    ),
+                    _ => Some(rest.to_string()),
+                }
+            } else {
+                None
+            };
+
+            if let Some(command) = apply(config, types, opt, &format!("-{c}"), value)? {
+                return Ok(Some(command));
+            }
+            if takes_value {
+                break;
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// The file types selected with '--type' and '--type-not', which are only looked up once every
+// '--type-add' was seen
+#[derive(Debug, Default)]
+struct Types {
+    added: Vec<(String, String)>,
+    selected: Vec<String>,
+    negated: Vec<String>,
+}
+
+impl Types {
+    // Selected types are searched like '--include' globs and negated ones skipped like
+    // '--exclude' globs
+    fn resolve(self, config: &mut Config) -> Result<(), ArgsError> {
+        let globs = |name: &str| -> Result<Vec<String>, ArgsError> {
+            let builtin = TYPES.iter().filter(|(type_name, _)| *type_name == name);
+            let added = self.added.iter().filter(|(type_name, _)| type_name == name);
+            let globs: Vec<String> = builtin
+                .flat_map(|(_, globs)| globs.iter().map(|glob| glob.to_string()))
+                .chain(added.map(|(_, glob)| glob.clone()))
+                .collect();
+
+            match globs.is_empty() {
+                true => Err(error(format!("unknown file type '{name}'"))),
+                false => Ok(globs),
+            }
+        };
+
+        for name in &self.selected {
+            config.include.extend(globs(name)?);
+        }
+        for name in &self.negated {
+            config.exclude.extend(globs(name)?);
+        }
+        Ok(())
+    }
+}
+
+// Returns a command when the option ends parsing early, like '--help' does
+fn apply(
+    config: &mut Config,
+    types: &mut Types,
+    opt: Opt,
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<Command>, ArgsError> {
+    let number = |value: Option<String>| -> Result<usize, ArgsError> {
+        let value = value.unwrap_or_default();
+        value.parse().map_err(|_| {
+            error(format!(
+                "invalid value '{value}' for option '{name}': expected a non-negative number"
+            ))
+        })
+    };
+
+    match opt {
+        Opt::Pattern => config.patterns.push(value.unwrap_or_default()),
+        Opt::File => config.pattern_files.push(value.unwrap_or_default()),
+        Opt::IgnoreCase => config.ignore_case = true,
+        Opt::SmartCase => config.smart_case = true,
+        Opt::CaseSensitive => {
+            config.ignore_case = false;
+            config.smart_case = false;
+        }
+        Opt::Regex => config.regex = true,
+        Opt::FixedStrings => config.regex = false,
+        Opt::Fuzzy => config.fuzzy = Some(number(value)?),
+        Opt::WordRegexp => config.word_regexp = true,
+        Opt::LineRegexp => config.line_regexp = true,
+        Opt::Multiline => config.multiline = true,
+        Opt::LineNumber => config.line_number = true,
+        Opt::InvertMatch => config.invert_match = true,
+        Opt::Count => config.output = OutputMode::Count,
+        Opt::FilesWithMatches => config.output = OutputMode::FilesWithMatches,
+        Opt::FilesWithoutMatch => config.output = OutputMode::FilesWithoutMatch,
+        Opt::Json => config.output = OutputMode::Json,
+        Opt::Binary => config.binary = true,
+        Opt::Decompress => config.decompress = true,
+        Opt::Encoding => {
+            let value = value.unwrap_or_default();
+            config.encoding = Some(Encoding::from_name(&value).ok_or_else(|| {
+                error(format!(
+                    "invalid value '{value}' for option '{name}': expected 'utf-8', 'utf-16le', \
+                     'utf-16be', 'latin1' or 'windows-1252'"
+                ))
+            })?);
+        }
+        Opt::Replace => config.replace = value,
+        Opt::InPlace => config.in_place = true,
+        Opt::MaxCount => config.max_count = Some(number(value)? as u64),
+        Opt::AfterContext => config.after_context = number(value)?,
+        Opt::BeforeContext => config.before_context = number(value)?,
+        Opt::Context => {
+            let lines = number(value)?;
+            config.before_context = lines;
+            config.after_context = lines;
+        }
+        Opt::Include => config.include.push(value.unwrap_or_default()),
+        Opt::Exclude => config.exclude.push(value.unwrap_or_default()),
+        Opt::Type => types.selected.push(value.unwrap_or_default()),
+        Opt::TypeNot => types.negated.push(value.unwrap_or_default()),
+        Opt::TypeAdd => {
+            let value = value.unwrap_or_default();
+            let (type_name, globs) = value
+                .split_once(':')
+                .filter(|(type_name, globs)| !type_name.is_empty() && !globs.is_empty())
+                .ok_or_else(|| {
+                    error(format!(
+                        "invalid value '{value}' for option '{name}': expected TYPE:GLOB[,GLOB]..."
+                    ))
+                })?;
+            types.added.extend(
+                globs
+                    .split(',')
+                    .map(|glob| (type_name.to_string(), glob.to_string())),
+            );
+        }
+        Opt::Follow => config.follow_links = true,
+        Opt::NoIgnore => config.no_ignore = true,
+        Opt::Hidden => config.hidden = true,
+        Opt::Threads => config.threads = number(value)?,
+        Opt::Ordered => config.ordered = true,
+        Opt::Mmap => config.mmap = MmapChoice::Always,
+        Opt::NoMmap => config.mmap = MmapChoice::Never,
+        Opt::Color => {
+            config.color = match value.as_deref() {
+                Some("auto") => ColorChoice::Auto,
+                Some("always") => ColorChoice::Always,
+                Some("never") => ColorChoice::Never,
+                _ => {
+                    return Err(error(format!(
+                    "invalid value '{}' for option '{name}': expected 'auto', 'always' or 'never'",
+                    value.unwrap_or_default()
+                )))
+                }
+            }
+        }
+        // Already handled by 'Command::parse' before the configuration file is read
+        Opt::NoConfig => config.no_config = true,
+        Opt::Help => return Ok(Some(Command::Help)),
+        Opt::Version => return Ok(Some(Command::Version)),
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, ArgsError> {
+        let args: Vec<String> = std::iter::once("minigrep")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        Command::parse_with_env(&args, || Ok(None), |_| false)
+    }
+
+    fn config(args: &[&str]) -> Config {
+        match parse(args) {
+            Ok(Command::Search(config)) => config,
+            other => panic!("expected a search, got {other:?}"),
+        }
+    }
+
+    fn message(args: &[&str]) -> String {
+        parse(args).unwrap_err().message
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config = config(&[
+            "-in",
+            "--invert-match",
+            "-C2",
+            "--after-context=5",
+            "us",
+            "poem.txt",
+        ]);
+
+        assert!(config.ignore_case);
+        assert!(!config.smart_case);
+        assert!(config.line_number);
+        assert!(config.invert_match);
+        assert_eq!(OutputMode::Lines, config.output);
+        assert_eq!(2, config.before_context);
+        assert_eq!(5, config.after_context);
+        assert_eq!(vec!["us"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn multiple_operands_and_end_of_options() {
+        let config = config(&["-c", "--", "-v", "a.txt", "-", "--include"]);
+
+        assert_eq!(OutputMode::Count, config.output);
+        assert!(!config.invert_match);
+        assert_eq!(vec!["-v"], config.patterns);
+        assert_eq!(vec!["a.txt", "-", "--include"], config.paths);
+    }
+
+    #[test]
+    fn fuzzy() {
+        assert_eq!(Some(2), config(&["--fuzzy", "2", "us"]).fuzzy);
+        assert_eq!(None, config(&["us"]).fuzzy);
+        assert_eq!(
+            "option '--fuzzy' can't be combined with '--word-regexp'",
+            message(&["--fuzzy=1", "-w", "us"])
+        );
+        assert_eq!(
+            "invalid value '-1' for option '--fuzzy': expected a non-negative number",
+            message(&["--fuzzy=-1", "us"])
+        );
+    }
+
+    #[test]
+    fn index_subcommand() {
+        let paths = |args: &[&str]| match parse(args) {
+            Ok(Command::Index(config)) => (config.paths, config.hidden),
+            other => panic!("expected an index command, got {other:?}"),
+        };
+
+        assert_eq!((vec![".".to_string()], false), paths(&["index"]));
+        assert_eq!(
+            (vec!["src".to_string(), "docs".to_string()], true),
+            paths(&["index", "--hidden", "src", "docs"])
+        );
+        assert_eq!(vec!["index"], config(&["--", "index"]).patterns);
+        assert_eq!(vec!["poem.txt"], config(&["-e", "index", "poem.txt"]).paths);
+    }
+
+    #[test]
+    fn multiline() {
+        assert!(config(&["-U", r"frog\nTo", "poem.txt"]).multiline);
+        assert!(!config(&["frog"]).multiline);
+        assert_eq!(
+            "option '--replace' can't be combined with '--multiline'",
+            message(&["--multiline", "-r", "toad", "frog"])
+        );
+    }
+
+    #[test]
+    fn word_and_line_regexp() {
+        let config = config(&["-wx", "us"]);
+
+        assert!(config.word_regexp);
+        assert!(config.line_regexp);
+        assert!(!config.regex);
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = config(&["-e", "us", "--pattern=them", "-fpatterns.txt", "poem.txt"]);
+
+        assert_eq!(vec!["us", "them"], config.patterns);
+        assert_eq!(vec!["patterns.txt"], config.pattern_files);
+        assert_eq!(vec!["poem.txt"], config.paths);
+    }
+
+    #[test]
+    fn output_modes() {
+        assert_eq!(OutputMode::FilesWithMatches, config(&["-l", "us"]).output);
+        assert_eq!(OutputMode::FilesWithoutMatch, config(&["-cL", "us"]).output);
+        assert_eq!(OutputMode::Json, config(&["--json", "us"]).output);
+        assert_eq!(Some(3), config(&["-m3", "us"]).max_count);
+        assert_eq!(None, config(&["us"]).max_count);
+        assert!(config(&["-a", "us"]).binary);
+        assert_eq!(
+            Some("$1".to_string()),
+            config(&["-r$1", "--in-place", "us", "poem.txt"]).replace
+        );
+    }
+
+    #[test]
+    fn threads() {
+        assert_eq!(0, config(&["us"]).threads);
+        assert_eq!(4, config(&["-j4", "us"]).threads);
+        assert!(config(&["--threads", "2", "--ordered", "us"]).ordered);
+    }
+
+    #[test]
+    fn mmap_choice() {
+        assert_eq!(MmapChoice::Auto, config(&["us"]).mmap);
+        assert_eq!(MmapChoice::Always, config(&["--mmap", "us"]).mmap);
+        // The last one wins
+        assert_eq!(
+            MmapChoice::Never,
+            config(&["--mmap", "--no-mmap", "us"]).mmap
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(None, config(&["us"]).encoding);
+        assert_eq!(
+            Some(Encoding::Utf16Le),
+            config(&["--encoding", "UTF-16LE", "us"]).encoding
+        );
+        assert_eq!(
+            "invalid value 'ebcdic' for option '--encoding': expected 'utf-8', 'utf-16le', \
+             'utf-16be', 'latin1' or 'windows-1252'",
+            message(&["--encoding=ebcdic", "us"])
+        );
+        assert_eq!(
+            "option '--in-place' can't be combined with '--encoding'",
+            message(&[
+                "-r",
+                "them",
+                "--in-place",
+                "--encoding=latin1",
+                "us",
+                "poem.txt"
+            ])
+        );
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(ColorChoice::Auto, config(&["us"]).color);
+        assert_eq!(ColorChoice::Never, config(&["--color=never", "us"]).color);
+        assert_eq!(
+            ColorChoice::Always,
+            config(&["--color", "always", "us"]).color
+        );
+        assert_eq!(
+            "invalid value 'sometimes' for option '--color': expected 'auto', 'always' or 'never'",
+            message(&["--color=sometimes", "us"])
+        );
+    }
+
+    #[test]
+    fn standard_input_by_default() {
+        assert_eq!(vec!["-"], config(&["us"]).paths);
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert!(matches!(parse(&["--help"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["-iV", "query"]), Ok(Command::Version)));
+    }
+
+    #[test]
+    fn environment_fallback() {
+        let args: Vec<String> = vec!["minigrep".into(), "us".into(), "poem.txt".into()];
+
+        let command =
+            Command::parse_with_env(&args, || Ok(None), |name| name == "IGNORE_CASE").unwrap();
+        match command {
+            Command::Search(config) => assert!(config.ignore_case && !config.regex),
+            other => panic!("expected a search, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn config_file_defaults() {
+        let parse = |defaults: &[&str], args: &[&str]| {
+            let args: Vec<String> = std::iter::once("minigrep")
+                .chain(args.iter().copied())
+                .map(String::from)
+                .collect();
+            let defaults = defaults.iter().map(|arg| arg.to_string()).collect();
+            Command::parse_with_env(
+                &args,
+                || Ok(Some(("rc".to_string(), defaults))),
+                |name| name == "REGEX",
+            )
+        };
+        let config = |defaults: &[&str], args: &[&str]| match parse(defaults, args) {
+            Ok(Command::Search(config)) => config,
+            other => panic!("expected a search, got {other:?}"),
+        };
+
+        // The environment, then the file, then the command line
+        let defaults = [
+            "-F",
+            "--ignore-case",
+            "--exclude=target",
+            "--color",
+            "never",
+        ];
+        let searched = config(
+            &defaults,
+            &["-s", "--color=always", "--exclude", "docs", "us"],
+        );
+        assert!(!searched.regex && !searched.ignore_case);
+        assert_eq!(ColorChoice::Always, searched.color);
+        assert_eq!(vec!["target", "docs"], searched.exclude);
+        assert!(config(&[], &["us"]).regex);
+        assert!(config(&defaults, &["-E", "us"]).regex);
+
+        let indexed = match parse(&defaults, &["index"]) {
+            Ok(Command::Index(config)) => config,
+            other => panic!("expected an index command, got {other:?}"),
+        };
+        assert_eq!(vec!["target"], indexed.exclude);
+
+        assert_eq!(
+            "in config file 'rc': unknown option '--colour'",
+            parse(&["--colour"], &["us"]).unwrap_err().message
+        );
+        assert_eq!(
+            "in config file 'rc': only options can be given, found 'us'",
+            parse(&["us"], &["them"]).unwrap_err().message
+        );
+        assert_eq!(
+            "in config file 'rc': options '--pattern' and '--file' can only be given on the \
+             command line",
+            parse(&["-e", "them"], &["us", "poem.txt"])
+                .unwrap_err()
+                .message
+        );
+        assert_eq!(
+            "in config file 'rc': options '--pattern' and '--file' can only be given on the \
+             command line",
+            parse(&["--file=patterns.txt"], &["us"])
+                .unwrap_err()
+                .message
+        );
+
+        for option in ["--help", "-V", "-iV"] {
+            assert_eq!(
+                "in config file 'rc': options '--help' and '--version' can only be given on the \
+                 command line",
+                parse(&[option], &["us"]).unwrap_err().message
+            );
+        }
+        assert!(matches!(parse(&["-i"], &["--help"]), Ok(Command::Help)));
+
+        // '--no-config' skips the file, but not when it's the value of another option
+        assert_eq!(
+            vec!["us"],
+            config(&["--colour"], &["--no-config", "us"]).patterns
+        );
+        let searched = config(&["-i"], &["-e", "--no-config", "poem.txt"]);
+        assert_eq!(vec!["--no-config"], searched.patterns);
+        assert!(searched.ignore_case);
+        assert!(config(&["-i"], &["--", "--no-config"]).ignore_case);
+    }
+
+    #[test]
+    fn file_types() {
+        let config = config(&[
+            "--type-add=web:*.html,*.css",
+            "-tweb",
+            "--type",
+            "rust",
+            "-T",
+            "md",
+            "us",
+        ]);
+        assert_eq!(vec!["*.html", "*.css", "*.rs"], config.include);
+        assert_eq!(vec!["*.md", "*.markdown"], config.exclude);
+
+        assert_eq!("unknown file type 'web'", message(&["-t", "web", "us"]));
+        assert_eq!(
+            "invalid value 'web' for option '--type-add': expected TYPE:GLOB[,GLOB]...",
+            message(&["--type-add", "web", "us"])
+        );
+    }
+
+    #[test]
+    fn errors_name_the_bad_flag() {
+        assert_eq!(
+            "unknown option '--colour'",
+            message(&["--colour", "us", "poem.txt"])
+        );
+        assert_eq!("unknown option '-q'", message(&["-iq", "us", "poem.txt"]));
+        assert_eq!(
+            "option '-A' requires a value",
+            message(&["us", "poem.txt", "-A"])
+        );
+        assert_eq!(
+            "option '--count' doesn't take a value",
+            message(&["--count=3", "us", "poem.txt"])
+        );
+        assert_eq!(
+            "invalid value 'two' for option '--context': expected a non-negative number",
+            message(&["--context", "two", "us", "poem.txt"])
+        );
+        assert_eq!("missing search query", message(&[]));
+        assert_eq!(
+            "option '--in-place' requires '--replace'",
+            message(&["--in-place", "us", "poem.txt"])
+        );
+        assert_eq!(
+            "option '--in-place' can't edit standard input",
+            message(&["--in-place", "-r", "them", "us"])
+        );
+        for (option, name) in [
+            ("-v", "invert-match"),
+            ("-c", "count"),
+            ("-l", "files-with-matches"),
+            ("-L", "files-without-match"),
+            ("-m1", "max-count"),
+        ] {
+            assert_eq!(
+                format!("option '--in-place' can't be combined with '--{name}'"),
+                message(&["--in-place", "-r", "them", option, "us", "poem.txt"])
+            );
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::literal;
+
+// Unicode simple case folding, which maps every character to exactly one character. Unlike
+// 'to_lowercase' it never changes the number of characters in a string, so matches found by
+// comparing folded characters are also matches in the original text, at the same offsets.
+//
+// Two strings are equal ignoring case when they are equal after folding every character. Simple
+// folding doesn't expand characters, so 'ß' matches 'ẞ' but not 'ss', and the Turkish 'İ' only
+// matches itself instead of an 'i' followed by a combining dot
+
+// Characters whose folded form isn't their lowercase form, usually because they already are
+// lowercase variants of another letter
+const EXCEPTIONS: &[(char, char)] = &[
+    ('\u{b5}', 'μ'),
+    // The iota subscript, which combines with the letter before it
+    ('\u{345}', 'ι'),
+    ('ſ', 's'),
+    ('ς', 'σ'),
+    ('ϐ', 'β'),
+    ('ϑ', 'θ'),
+    ('ϕ', 'φ'),
+    ('ϖ', 'π'),
+    ('ϰ', 'κ'),
+    ('ϱ', 'ρ'),
+    ('ϵ', 'ε'),
+    // Old Cyrillic letter forms
+    ('\u{1c80}', 'в'),
+    ('\u{1c81}', 'д'),
+    ('\u{1c82}', 'о'),
+    ('\u{1c83}', 'с'),
+    ('\u{1c84}', 'т'),
+    ('\u{1c85}', 'т'),
+    ('\u{1c86}', 'ъ'),
+    ('\u{1c87}', 'ѣ'),
+    ('\u{1c88}', 'ꙋ'),
+    ('ẛ', 'ṡ'),
+    ('\u{1fbe}', 'ι'),
+];
+
+pub fn fold(c: char) -> char {
+    if c.is_ascii() {
+        return c.to_ascii_lowercase();
+    }
+
+    match c {
+        // Cherokee is the one script that folds to uppercase
+        '\u{13a0}'..='\u{13f5}' => c,
+        '\u{13f8}'..='\u{13fd}' => char::from_u32(c as u32 - 8).unwrap_or(c),
+        '\u{ab70}'..='\u{abbf}' => char::from_u32(c as u32 - 0xab70 + 0x13a0).unwrap_or(c),
+        _ => {
+            if let Some(&(_, folded)) = EXCEPTIONS.iter().find(|&&(from, _)| from == c) {
+                return folded;
+            }
+            // Characters like 'İ' that lowercase to several characters have no simple folding
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(lower), None) => lower,
+                _ => c,
+            }
+        }
+    }
+}
+
+pub fn fold_str(text: &str) -> String {
+    text.chars().map(fold).collect()
+}
+
+// Byte offsets in 'text' of every non-overlapping occurrence of 'folded', which was already
+// folded with 'fold_str'. Compares one character at a time without copying 'text'
+pub fn find_all(folded: &str, text: &str) -> Vec<(usize, usize)> {
+    if folded.is_empty() {
+        return vec![(0, 0)];
+    }
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while let Some((found, end)) = find_at(folded, text, start) {
+        matches.push((found, end));
+        start = end;
+    }
+
+    matches
+}
+
+// The first occurrence of 'folded' in 'text' at or after the byte offset 'start'
+pub fn find_at(folded: &str, text: &str, mut start: usize) -> Option<(usize, usize)> {
+    let first = match folded.chars().next() {
+        Some(first) => first,
+        None => return (start <= text.len()).then_some((start, start)),
+    };
+
+    // Apart from 'k' and 's', which the Kelvin sign and the long s fold to, every character
+    // folding to an ASCII letter is ASCII itself, so the candidates can be found byte by byte
+    if first.is_ascii() && first != 'k' && first != 's' {
+        let (lower, upper) = (first as u8, first.to_ascii_uppercase() as u8);
+        loop {
+            start += literal::memchr2(lower, upper, &text.as_bytes()[start..])?;
+            if let Some(end) = matches_at(folded, text, start) {
+                return Some((start, end));
+            }
+            start += 1;
+        }
+    }
+
+    while let Some(c) = text[start..].chars().next() {
+        if let Some(end) = matches_at(folded, text, start) {
+            return Some((start, end));
+        }
+        start += c.len_utf8();
+    }
+
+    None
+}
+
+// End of the match of 'folded' starting at 'start', if there is one
+fn matches_at(folded: &str, text: &str, start: usize) -> Option<usize> {
+    let mut chars = text[start..].char_indices();
+    folded
+        .chars()
+        .try_fold(start, |_, expected| match chars.next() {
+            Some((i, c)) if fold(c) == expected => Some(start + i + c.len_utf8()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, text: &str) -> Vec<(usize, usize)> {
+        find_all(&fold_str(query), text)
+    }
+
+    #[test]
+    fn folds_single_characters() {
+        assert_eq!('a', fold('A'));
+        assert_eq!('ß', fold('ẞ'));
+        assert_eq!('σ', fold('ς'));
+        assert_eq!('σ', fold('Σ'));
+        assert_eq!('İ', fold('İ'));
+        assert_eq!('Ꭰ', fold('ꭰ'));
+        assert_eq!('Ꭰ', fold('Ꭰ'));
+        assert_eq!('ι', fold('\u{345}'));
+        assert_eq!('в', fold('\u{1c80}'));
+        assert_eq!('т', fold('\u{1c85}'));
+        assert_eq!('ꙋ', fold('\u{1c88}'));
+    }
+
+    #[test]
+    fn matches_in_the_original_text() {
+        assert_eq!(
+            vec![(0, 7), (16, 23)],
+            find("STRAẞE", "straße STRASSE Straße")
+        );
+        assert_eq!(vec![(0, 6)], find("σας", "ΣΑΣ"));
+        assert_eq!(vec![(0, 9)], find("ВОТ", "\u{1c80}\u{1c82}\u{1c84}"));
+        assert!(find("is", "İstanbul").is_empty());
+        assert_eq!(vec![(1, 3), (3, 5)], find("aA", "xaaAAa"));
+        assert_eq!(vec![(0, 0)], find("", "text"));
+    }
+}
@@ -0,0 +1,115 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::args::ArgsError;
+
+// Default options for every search, read from a configuration file. The file holds one argument
+// per line, exactly as it would be written on the command line, so an option and its value go
+// either on one line as '--exclude=target' or on two. Blank lines and lines starting with '#'
+// are skipped, and the whitespace around each line is trimmed:
+//
+//   # Ignore case unless the query has an uppercase letter
+//   --smart-case
+//   --type-add=web:*.html,*.css,*.js
+//   --exclude=node_modules
+//
+// The file is MINIGREP_CONFIG when that is set, and 'minigrep/config' in the XDG config
+// directory otherwise. Setting MINIGREP_CONFIG to an empty value, or passing '--no-config',
+// skips the file. Queries only go on the command line, so '--pattern' and '--file' are errors
+// in the file, and so are '--help' and '--version', which would stop every search.
+//
+// Later settings win over earlier ones: the built-in defaults come first, then the IGNORE_CASE
+// and REGEX environment variables, then the file, then the command line. Options that can be
+// given several times, like '--exclude', add to the ones before them instead
+
+pub const ENV_VAR: &str = "MINIGREP_CONFIG";
+
+// The arguments in the configuration file, with its path for error messages. None when there is
+// no file. A missing file is only an error when MINIGREP_CONFIG names it
+pub fn load() -> Result<Option<(String, Vec<String>)>, ArgsError> {
+    let Some((path, named)) = path(|name| env::var_os(name)) else {
+        return Ok(None);
+    };
+    let display = path.display().to_string();
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some((display, parse(&contents)))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !named => Ok(None),
+        Err(err) => Err(ArgsError {
+            message: format!("can't read config file '{display}': {err}"),
+        }),
+    }
+}
+
+// Where the file is, and whether MINIGREP_CONFIG named it
+fn path(var: impl Fn(&str) -> Option<OsString>) -> Option<(PathBuf, bool)> {
+    let set = |name: &str| var(name).filter(|value| !value.is_empty());
+
+    if let Some(path) = var(ENV_VAR) {
+        return (!path.is_empty()).then(|| (PathBuf::from(path), true));
+    }
+    let dir = match set("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(set("HOME")?).join(".config"),
+    };
+    Some((dir.join("minigrep").join("config"), false))
+}
+
+fn parse(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_argument_per_line() {
+        let contents = "\
+# Defaults
+--smart-case
+
+  --exclude=target
+-C
+2
+";
+        assert_eq!(
+            vec!["--smart-case", "--exclude=target", "-C", "2"],
+            parse(contents)
+        );
+    }
+
+    #[test]
+    fn finds_the_file() {
+        let vars = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+
+        assert_eq!(
+            Some((PathBuf::from("/etc/minigrep"), true)),
+            path(vars(&[(ENV_VAR, "/etc/minigrep"), ("HOME", "/home/me")]))
+        );
+        assert_eq!(None, path(vars(&[(ENV_VAR, ""), ("HOME", "/home/me")])));
+        assert_eq!(
+            Some((PathBuf::from("/xdg/minigrep/config"), false)),
+            path(vars(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/me")]))
+        );
+        assert_eq!(
+            Some((PathBuf::from("/home/me/.config/minigrep/config"), false)),
+            path(vars(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/me")]))
+        );
+        assert_eq!(None, path(vars(&[])));
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+// Searching inside compressed files with '-z'. The format is recognized by the magic bytes its
+// stream starts with rather than by the file name, so rotated logs like 'app.log.1' work too.
+// Every decoder sits behind the cargo feature of the same name, and a build without it reports
+// files in that format as an error instead of searching the compressed bytes
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+const MAGIC: &[(Format, &[u8])] = &[
+    (Format::Gzip, b"\x1f\x8b"),
+    (Format::Bzip2, b"BZh"),
+    (Format::Xz, b"\xfd7zXZ\x00"),
+    (Format::Zstd, b"\x28\xb5\x2f\xfd"),
+];
+
+impl Format {
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        let &(format, magic) = MAGIC.iter().find(|(_, magic)| header.starts_with(magic))?;
+
+        // bzip2 follows its magic with the block size from '1' to '9', which keeps text that
+        // happens to start with "BZh" from being taken for it
+        if format == Format::Bzip2 && !matches!(header.get(magic.len()), Some(b'1'..=b'9')) {
+            return None;
+        }
+        Some(format)
+    }
+
+    // Also the name of the cargo feature enabling the decoder
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Bzip2 => "bzip2",
+            Format::Xz => "xz",
+            Format::Zstd => "zstd",
+        }
+    }
+}
+
+// Wraps 'reader' in a decoder when it starts with a compressed stream, otherwise returns it as it
+// is. Concatenated streams, as left behind by 'cat a.gz b.gz', are decompressed one after another
+pub fn decompress<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    match Format::detect(reader.fill_buf()?) {
+        Some(format) => Ok(Box::new(BufReader::new(decoder(format, reader)?))),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "gzip", feature = "bzip2", feature = "xz", feature = "zstd")),
+    allow(unused_variables)
+)]
+fn decoder<'a>(format: Format, reader: impl BufRead + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    match format {
+        #[cfg(feature = "gzip")]
+        Format::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "bzip2")]
+        Format::Bzip2 => Ok(Box::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        #[cfg(feature = "xz")]
+        Format::Xz => Ok(Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader))),
+        #[cfg(feature = "zstd")]
+        Format::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "compressed with {0}, but minigrep was built without the '{0}' feature",
+                format.name()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "one\ntwo\nthree\n";
+
+    fn decompressed(compressed: &[u8]) -> String {
+        let mut text = String::new();
+        decompress(compressed)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(Some(Format::Gzip), Format::detect(b"\x1f\x8b\x08\x00"));
+        assert_eq!(Some(Format::Bzip2), Format::detect(b"BZh91AY&SY"));
+        assert_eq!(Some(Format::Xz), Format::detect(b"\xfd7zXZ\x00\x00\x04"));
+        assert_eq!(Some(Format::Zstd), Format::detect(b"\x28\xb5\x2f\xfd\x04"));
+        assert_eq!(None, Format::detect(b"BZ"));
+        assert_eq!(None, Format::detect(b"BZhello"));
+        assert_eq!(None, Format::detect(b"BZh"));
+        assert_eq!(TEXT, decompressed(TEXT.as_bytes()));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        // Two members, like two gzipped files concatenated
+        let mut compressed = Vec::new();
+        for part in ["one\ntwo\n", "three\n"] {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+
+        assert_eq!(TEXT, decompressed(&compressed));
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2() {
+        let mut compressed = Vec::new();
+        bzip2::read::BzEncoder::new(TEXT.as_bytes(), bzip2::Compression::default())
+            .read_to_end(&mut compressed)
+            .unwrap();
+
+        assert_eq!(TEXT, decompressed(&compressed));
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz() {
+        let mut compressed = Vec::new();
+        xz2::read::XzEncoder::new(TEXT.as_bytes(), 6)
+            .read_to_end(&mut compressed)
+            .unwrap();
+
+        assert_eq!(TEXT, decompressed(&compressed));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd() {
+        let compressed = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+
+        assert_eq!(TEXT, decompressed(&compressed));
+    }
+}
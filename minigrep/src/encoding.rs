@@ -0,0 +1,376 @@
+use std::char::REPLACEMENT_CHARACTER;
+use std::env;
+use std::io::{self, BufRead, Read, Write};
+
+// Searching text that isn't UTF-8. An input starting with a byte order mark is read in the
+// encoding the mark announces, and '--encoding' reads every input in the given one instead.
+// Either way the text is transcoded to UTF-8 before it is searched and the mark is skipped, so
+// offsets count bytes of the UTF-8 text after the mark rather than bytes of the file.
+//
+// Results are printed in the encoding of the terminal, as named by the locale, with characters
+// it can't show replaced by '?'. Output that doesn't go to a terminal is always UTF-8
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    // ISO-8859-1, where every byte is the code point of the same value
+    Latin1,
+    Windows1252,
+}
+
+const BOMS: &[(Encoding, &[u8])] = &[
+    (Encoding::Utf8, b"\xef\xbb\xbf"),
+    (Encoding::Utf16Le, b"\xff\xfe"),
+    (Encoding::Utf16Be, b"\xfe\xff"),
+];
+
+// The characters of the bytes 0x80 to 0x9f in Windows-1252, which is Latin-1 everywhere else.
+// The five bytes it leaves undefined stay the control characters they are in Latin-1
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+impl Encoding {
+    // Case and punctuation don't matter, so 'UTF-16LE', 'utf16le' and 'utf_16le' are the same
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        let name: String = name
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        match name.as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso88591" => Some(Encoding::Latin1),
+            "windows1252" | "cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    // The encoding announced by a byte order mark at the start of 'header', and the length of
+    // the mark
+    pub fn sniff(header: &[u8]) -> Option<(Encoding, usize)> {
+        BOMS.iter()
+            .find(|(_, bom)| header.starts_with(bom))
+            .map(|&(encoding, bom)| (encoding, bom.len()))
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let mut text = String::with_capacity(bytes.len());
+        self.decode_into(bytes, true, &mut text);
+        text
+    }
+
+    // Appends the characters at the start of 'bytes' to 'text' and returns how many bytes they
+    // took. A character cut off at the end is left for the next call, unless 'end' says there
+    // is nothing after it. Invalid sequences become U+FFFD
+    fn decode_into(self, bytes: &[u8], end: bool, text: &mut String) -> usize {
+        match self {
+            Encoding::Utf8 => {
+                let mut used = 0;
+                loop {
+                    let err = match std::str::from_utf8(&bytes[used..]) {
+                        Ok(valid) => {
+                            text.push_str(valid);
+                            return bytes.len();
+                        }
+                        Err(err) => err,
+                    };
+                    let valid = &bytes[used..used + err.valid_up_to()];
+                    text.push_str(&String::from_utf8_lossy(valid));
+                    used += valid.len();
+
+                    match err.error_len() {
+                        Some(len) => used += len,
+                        None if !end => return used,
+                        None => used = bytes.len(),
+                    }
+                    text.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let unit = |i: usize| {
+                    let pair = [bytes[2 * i], bytes[2 * i + 1]];
+                    match self {
+                        Encoding::Utf16Le => u16::from_le_bytes(pair),
+                        _ => u16::from_be_bytes(pair),
+                    }
+                };
+                let mut units = bytes.len() / 2;
+                // A high surrogate waits for the low one completing it
+                if !end && units > 0 && (0xd800..0xdc00).contains(&unit(units - 1)) {
+                    units -= 1;
+                }
+
+                text.extend(
+                    char::decode_utf16((0..units).map(unit))
+                        .map(|c| c.unwrap_or(REPLACEMENT_CHARACTER)),
+                );
+                match end {
+                    true if bytes.len() % 2 == 1 => text.push(REPLACEMENT_CHARACTER),
+                    true => {}
+                    false => return units * 2,
+                }
+                bytes.len()
+            }
+            Encoding::Latin1 => {
+                text.extend(bytes.iter().map(|&byte| byte as char));
+                bytes.len()
+            }
+            Encoding::Windows1252 => {
+                text.extend(bytes.iter().map(|&byte| match byte {
+                    0x80..=0x9f => WINDOWS_1252[byte as usize - 0x80],
+                    _ => byte as char,
+                }));
+                bytes.len()
+            }
+        }
+    }
+
+    // Appends 'text' to 'out', with '?' for the characters this encoding can't represent
+    fn encode_into(self, text: &str, out: &mut Vec<u8>) {
+        match self {
+            Encoding::Utf8 => out.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => out.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => out.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => out.extend(text.chars().map(|c| u8::try_from(c).unwrap_or(b'?'))),
+            Encoding::Windows1252 => out.extend(text.chars().map(|c| {
+                match WINDOWS_1252.iter().position(|&special| special == c) {
+                    Some(i) => 0x80 + i as u8,
+                    None => u8::try_from(c).unwrap_or(b'?'),
+                }
+            })),
+        }
+    }
+}
+
+// How an input starting with 'header' has to be read: in which encoding, and how long the byte
+// order mark to skip is. None when it can be searched as it is. 'encoding' is the one asked for
+// with '--encoding', which wins over the mark
+pub fn detect(header: &[u8], encoding: Option<Encoding>) -> Option<(Encoding, usize)> {
+    match (encoding, Encoding::sniff(header)) {
+        // The mark of the encoding asked for is skipped all the same, a UTF-8 one included
+        (Some(encoding), Some((found, bom))) if encoding == found => Some((encoding, bom)),
+        (None, None) | (Some(Encoding::Utf8), _) => None,
+        (Some(encoding), _) => Some((encoding, 0)),
+        (None, found) => found,
+    }
+}
+
+// Transcodes 'reader' from 'encoding' to UTF-8 as it is read
+pub struct Decoder<R> {
+    reader: R,
+    encoding: Encoding,
+    text: String,
+    // Position of the next byte of 'text' to read
+    pos: usize,
+    // The start of a character that was cut off at the end of the last buffer of 'reader'
+    carry: Vec<u8>,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(reader: R, encoding: Encoding) -> Decoder<R> {
+        Decoder {
+            reader,
+            encoding,
+            text: String::new(),
+            pos: 0,
+            carry: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.text.len() {
+            self.text.clear();
+            self.pos = 0;
+
+            let available = self.reader.fill_buf()?;
+            let (len, end) = (available.len(), available.is_empty());
+            if end && self.carry.is_empty() {
+                break;
+            }
+            if self.carry.is_empty() {
+                let used = self.encoding.decode_into(available, end, &mut self.text);
+                self.carry.extend_from_slice(&available[used..]);
+            } else {
+                self.carry.extend_from_slice(available);
+                let used = self.encoding.decode_into(&self.carry, end, &mut self.text);
+                self.carry.drain(..used);
+            }
+            self.reader.consume(len);
+        }
+
+        Ok(&self.text.as_bytes()[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.text.len());
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+// Transcodes the UTF-8 written to it into 'encoding'. A character cut off at the end of one
+// write is completed by the next one
+pub struct Encoder<W: Write> {
+    out: W,
+    encoding: Encoding,
+    pending: Vec<u8>,
+    text: String,
+    encoded: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(out: W, encoding: Encoding) -> Encoder<W> {
+        Encoder {
+            out,
+            encoding,
+            pending: Vec::new(),
+            text: String::new(),
+            encoded: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if self.encoding == Encoding::Utf8 {
+            return self.out.write(bytes);
+        }
+
+        self.pending.extend_from_slice(bytes);
+        self.text.clear();
+        let used = Encoding::Utf8.decode_into(&self.pending, false, &mut self.text);
+        self.pending.drain(..used);
+
+        self.encoded.clear();
+        self.encoding.encode_into(&self.text, &mut self.encoded);
+        self.out.write_all(&self.encoded)?;
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// The encoding of the terminal, from the character set of the locale in the first of LC_ALL,
+// LC_CTYPE and LANG that is set. UTF-8 when the locale doesn't name one minigrep knows
+pub fn terminal() -> Encoding {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_default();
+    locale_encoding(&locale)
+}
+
+// Locales look like 'language_TERRITORY.charset@modifier'
+fn locale_encoding(locale: &str) -> Encoding {
+    let charset = locale.split_once('.').map_or("", |(_, charset)| charset);
+    let charset = charset.split('@').next().unwrap_or_default();
+    Encoding::from_name(charset).unwrap_or(Encoding::Utf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut bytes = b"\xff\xfe".to_vec();
+        Encoding::Utf16Le.encode_into(text, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Some(Encoding::Utf16Le), Encoding::from_name("UTF-16LE"));
+        assert_eq!(Some(Encoding::Latin1), Encoding::from_name("ISO-8859-1"));
+        assert_eq!(Some(Encoding::Windows1252), Encoding::from_name("cp1252"));
+        assert_eq!(None, Encoding::from_name("utf-16"));
+        assert_eq!(Encoding::Latin1, locale_encoding("de_DE.ISO-8859-1@euro"));
+        assert_eq!(Encoding::Utf8, locale_encoding("en_US.UTF-8"));
+        assert_eq!(Encoding::Utf8, locale_encoding("C"));
+    }
+
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(Some((Encoding::Utf16Le, 2)), detect(b"\xff\xfeI\x00", None));
+        assert_eq!(Some((Encoding::Utf8, 3)), detect(b"\xef\xbb\xbfI", None));
+        assert_eq!(None, detect(b"I'm", None));
+        assert_eq!(
+            Some((Encoding::Latin1, 0)),
+            detect(b"\xff\xfeI", Some(Encoding::Latin1))
+        );
+        assert_eq!(None, detect(b"\xff\xfeI", Some(Encoding::Utf8)));
+        assert_eq!(
+            Some((Encoding::Utf8, 3)),
+            detect(b"\xef\xbb\xbfI", Some(Encoding::Utf8))
+        );
+    }
+
+    #[test]
+    fn decodes() {
+        assert_eq!(
+            "na\u{ef}ve \u{20ac}",
+            Encoding::Windows1252.decode(b"na\xefve \x80")
+        );
+        assert_eq!(
+            "na\u{ef}ve \u{80}",
+            Encoding::Latin1.decode(b"na\xefve \x80")
+        );
+        assert_eq!(
+            "f\u{1f438}g",
+            Encoding::Utf16Be.decode(b"\x00f\xd8\x3d\xdc\x38\x00g")
+        );
+        assert_eq!(
+            "f\u{fffd}\u{fffd}",
+            Encoding::Utf16Le.decode(b"f\x00\x3d\xd8g")
+        );
+        assert_eq!(
+            "a\u{fffd}b\u{fffd}",
+            Encoding::Utf8.decode(b"a\xffb\xe2\x82")
+        );
+    }
+
+    #[test]
+    fn decodes_while_reading() {
+        let text = "I'm nobody! \u{1f438}\nWho are you?\n";
+        let bytes = utf16le(text);
+        // One byte at a time cuts every character in half, and the frog in four
+        let reader = io::BufReader::with_capacity(1, &bytes[2..]);
+        let mut decoded = String::new();
+        Decoder::new(reader, Encoding::Utf16Le)
+            .read_to_string(&mut decoded)
+            .unwrap();
+
+        assert_eq!(text, decoded);
+    }
+
+    #[test]
+    fn encodes_output() {
+        let mut out = Vec::new();
+        let mut encoder = Encoder::new(&mut out, Encoding::Windows1252);
+        let text = "caf\u{e9} \u{20ac}5 \u{1f438}\n".as_bytes();
+        // Split in the middle of the euro sign
+        encoder.write_all(&text[..8]).unwrap();
+        encoder.write_all(&text[8..]).unwrap();
+
+        assert_eq!(b"caf\xe9 \x805 ?\n".to_vec(), out);
+    }
+}
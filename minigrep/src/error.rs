@@ -0,0 +1,87 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::args::ArgsError;
+use crate::glob;
+use crate::regex;
+
+#[derive(Debug)]
+pub enum Error {
+    Args(ArgsError),
+    // Reading an input failed. 'path' is None until the error reaches the code that knows which
+    // input was being read
+    Io {
+        path: Option<String>,
+        source: io::Error,
+    },
+    Pattern(regex::Error),
+    Glob(glob::Error),
+    // Writing the results failed, which stops the whole search
+    Output(io::Error),
+}
+
+impl Error {
+    // Fills in the path of an input error that doesn't have one yet
+    pub fn with_path(self, name: &str) -> Error {
+        match self {
+            Error::Io { path: None, source } => Error::Io {
+                path: Some(name.to_string()),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Args(err) => write!(f, "{err}"),
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{path}: {source}"),
+            Error::Io { path: None, source } => write!(f, "{source}"),
+            Error::Pattern(err) => write!(f, "invalid pattern: {err}"),
+            Error::Glob(err) => write!(f, "{err}"),
+            Error::Output(err) => write!(f, "failed to write results: {err}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Args(err) => Some(err),
+            Error::Io { source, .. } => Some(source),
+            Error::Pattern(err) => Some(err),
+            Error::Glob(err) => Some(err),
+            Error::Output(err) => Some(err),
+        }
+    }
+}
+
+impl From<ArgsError> for Error {
+    fn from(err: ArgsError) -> Error {
+        Error::Args(err)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Error {
+        Error::Pattern(err)
+    }
+}
+
+impl From<glob::Error> for Error {
+    fn from(err: glob::Error) -> Error {
+        Error::Glob(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::Io { path: None, source }
+    }
+}
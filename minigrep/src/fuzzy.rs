@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use crate::case::{fold, fold_str};
+
+// Approximate matching for '--fuzzy K'. A match is a substring whose Levenshtein distance to the
+// query is at most K, so up to K characters were inserted, deleted or replaced.
+//
+// Matches are found with Myers' bit-parallel algorithm, which keeps one column of the dynamic
+// programming table in the bits of a few machine words and updates it with a handful of bitwise
+// operations per character of the text. Queries longer than 64 characters span several words,
+// as in Hyyrö's block-based variant. Searching takes time linear in the length of the text.
+//
+// Myers' algorithm only finds where matches end. Where a match starts is found by running it
+// again over the reversed text and query, anchored at the end of the match
+
+const BITS: usize = u64::BITS as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    // Edit distance between the matched text and the query
+    pub distance: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+    forward: Pattern,
+    reverse: Pattern,
+    max_distance: usize,
+    ignore_case: bool,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max_distance: usize, ignore_case: bool) -> Fuzzy {
+        let query: Vec<char> = match ignore_case {
+            true => fold_str(query).chars().collect(),
+            false => query.chars().collect(),
+        };
+        let reversed: Vec<char> = query.iter().rev().copied().collect();
+
+        Fuzzy {
+            forward: Pattern::new(&query),
+            reverse: Pattern::new(&reversed),
+            max_distance,
+            ignore_case,
+        }
+    }
+
+    // The non-overlapping matches in 'text' from left to right. Where several overlapping
+    // substrings are close enough, the one ending at the closest position is picked
+    pub fn find_all(&self, text: &str) -> Vec<Match> {
+        // Deleting the whole query is close enough, so even an empty line matches
+        if self.forward.len <= self.max_distance {
+            return vec![Match {
+                start: 0,
+                end: 0,
+                distance: self.forward.len,
+            }];
+        }
+
+        let mut matches = Vec::new();
+        let mut pos = 0;
+        while let Some((end, distance)) = self.find_end(text, pos) {
+            let start = self.find_start(text, pos, end, distance);
+            matches.push(Match {
+                start,
+                end,
+                distance,
+            });
+            pos = end;
+        }
+
+        matches
+    }
+
+    // The end and distance of the next match starting at or after 'pos'. The end positions
+    // within the distance come in runs, the best one in the first run wins
+    fn find_end(&self, text: &str, pos: usize) -> Option<(usize, usize)> {
+        let mut search = Search::new(&self.forward, false);
+        let mut best: Option<(usize, usize)> = None;
+
+        for (i, c) in text[pos..].char_indices() {
+            let distance = search.step(self.fold(c));
+            if distance <= self.max_distance {
+                if best.is_none_or(|(_, best)| distance < best) {
+                    best = Some((pos + i + c.len_utf8(), distance));
+                }
+            } else if best.is_some() {
+                break;
+            }
+        }
+
+        best
+    }
+
+    // The start of the shortest match ending at 'end' with 'distance', not before 'pos'
+    fn find_start(&self, text: &str, pos: usize, end: usize, distance: usize) -> usize {
+        let mut search = Search::new(&self.reverse, true);
+
+        for (i, c) in text[pos..end].char_indices().rev() {
+            if search.step(self.fold(c)) == distance {
+                return pos + i;
+            }
+        }
+
+        pos
+    }
+
+    fn fold(&self, c: char) -> char {
+        match self.ignore_case {
+            true => fold(c),
+            false => c,
+        }
+    }
+}
+
+// For every character of the query, a bit mask per block with the bits set where it occurs
+#[derive(Debug, Clone)]
+struct Pattern {
+    len: usize,
+    blocks: usize,
+    ascii: Vec<u64>,
+    other: HashMap<char, Vec<u64>>,
+}
+
+impl Pattern {
+    fn new(query: &[char]) -> Pattern {
+        let blocks = query.len().div_ceil(BITS).max(1);
+        let mut ascii = vec![0; 128 * blocks];
+        let mut other: HashMap<char, Vec<u64>> = HashMap::new();
+
+        for (i, &c) in query.iter().enumerate() {
+            let (block, bit) = (i / BITS, 1 << (i % BITS));
+            match c.is_ascii() {
+                true => ascii[c as usize * blocks + block] |= bit,
+                false => other.entry(c).or_insert_with(|| vec![0; blocks])[block] |= bit,
+            }
+        }
+
+        Pattern {
+            len: query.len(),
+            blocks,
+            ascii,
+            other,
+        }
+    }
+
+    fn mask(&self, c: char, block: usize) -> u64 {
+        match c.is_ascii() {
+            true => self.ascii[c as usize * self.blocks + block],
+            false => self.other.get(&c).map_or(0, |masks| masks[block]),
+        }
+    }
+}
+
+// One column of the table, stored as the vertical differences between neighbouring rows: bits
+// set in 'positive' and 'negative' mark rows one more or one less than the row above
+struct Search<'p> {
+    pattern: &'p Pattern,
+    positive: Vec<u64>,
+    negative: Vec<u64>,
+    // Distance in the last row, between the whole query and the text ending here
+    distance: usize,
+    // When anchored, matches have to start at the first character searched, otherwise anywhere
+    anchored: bool,
+}
+
+impl<'p> Search<'p> {
+    fn new(pattern: &'p Pattern, anchored: bool) -> Search<'p> {
+        Search {
+            pattern,
+            positive: vec![u64::MAX; pattern.blocks],
+            negative: vec![0; pattern.blocks],
+            distance: pattern.len,
+            anchored,
+        }
+    }
+
+    // Moves on to the next character of the text and returns the new distance
+    fn step(&mut self, c: char) -> usize {
+        // The difference carried from the row above the block, the top row is all zeros unless
+        // the match is anchored, where it counts the characters skipped
+        let mut carry: i32 = self.anchored as i32;
+        let last = self.pattern.blocks - 1;
+
+        for block in 0..self.pattern.blocks {
+            // The difference in the last row of the query, which for the last block isn't
+            // necessarily its highest bit
+            let high = match block == last {
+                true => 1 << ((self.pattern.len.max(1) - 1) % BITS),
+                false => 1 << (BITS - 1),
+            };
+            carry = self.advance(block, self.pattern.mask(c, block), carry, high);
+        }
+
+        match carry {
+            1 => self.distance += 1,
+            -1 => self.distance -= 1,
+            _ => {}
+        }
+        self.distance
+    }
+
+    fn advance(&mut self, block: usize, mut eq: u64, carry: i32, high: u64) -> i32 {
+        let (pv, mv) = (self.positive[block], self.negative[block]);
+        let xv = eq | mv;
+        if carry < 0 {
+            eq |= 1;
+        }
+        let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+        let mut ph = mv | !(xh | pv);
+        let mut mh = pv & xh;
+
+        let out = match (ph & high != 0, mh & high != 0) {
+            (true, _) => 1,
+            (_, true) => -1,
+            _ => 0,
+        };
+
+        ph <<= 1;
+        mh <<= 1;
+        match carry {
+            1 => ph |= 1,
+            -1 => mh |= 1,
+            _ => {}
+        }
+        self.positive[block] = mh | !(xv | ph);
+        self.negative[block] = ph & xv;
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, max_distance: usize, text: &str) -> Vec<(usize, usize, usize)> {
+        Fuzzy::new(query, max_distance, false)
+            .find_all(text)
+            .into_iter()
+            .map(|m| (m.start, m.end, m.distance))
+            .collect()
+    }
+
+    // The textbook dynamic programming versions, to check the bit-parallel one against. With
+    // 'anywhere' the match may start at any character of 'text' instead of only the first one.
+    // Returns the distance for every end position
+    fn distances(query: &[char], text: &[char], anywhere: bool) -> Vec<usize> {
+        let mut column: Vec<usize> = (0..=query.len()).collect();
+        let mut last_row = Vec::new();
+        for (j, &c) in text.iter().enumerate() {
+            let mut diagonal = column[0];
+            column[0] = if anywhere { 0 } else { j + 1 };
+            for (i, &q) in query.iter().enumerate() {
+                let next = (diagonal + (q != c) as usize)
+                    .min(column[i] + 1)
+                    .min(column[i + 1] + 1);
+                diagonal = column[i + 1];
+                column[i + 1] = next;
+            }
+            last_row.push(column[query.len()]);
+        }
+        last_row
+    }
+
+    #[test]
+    fn finds_typos() {
+        assert_eq!(vec![(9, 13, 0)], find("frog", 1, "like a f frog"));
+        assert_eq!(vec![(7, 10, 1)], find("frog", 1, "like a frgo"));
+        assert_eq!(vec![(7, 10, 1)], find("frog", 1, "like a fog"));
+        assert!(find("frog", 1, "like a dog").is_empty());
+        assert_eq!(vec![(8, 10, 2)], find("frog", 2, "like a dog"));
+        assert_eq!(vec![(0, 3, 1), (4, 7, 1)], find("cat", 1, "cut cot"));
+        assert_eq!(vec![(0, 0, 2)], find("us", 2, ""));
+        assert_eq!(vec![(0, 6, 1)], find("straße", 1, "straß"));
+    }
+
+    #[test]
+    fn ignores_case() {
+        let fuzzy = Fuzzy::new("NOBODY", 1, true);
+
+        assert_eq!(
+            vec![Match {
+                start: 4,
+                end: 10,
+                distance: 1
+            }],
+            fuzzy.find_all("I'm nobudy!")
+        );
+    }
+
+    #[test]
+    fn agrees_with_dynamic_programming() {
+        // Queries spanning one, two and three blocks
+        let text: Vec<char> =
+            "Then there's a pair of us - don't tell! They'd banish us, you know. "
+                .repeat(3)
+                .chars()
+                .collect();
+
+        for len in [10, 64, 70, 150] {
+            let query: Vec<char> = text[5..5 + len]
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| if i % 11 == 3 { 'x' } else { c })
+                .collect();
+
+            for (anchored, anywhere) in [(false, true), (true, false)] {
+                let pattern = Pattern::new(&query);
+                let mut search = Search::new(&pattern, anchored);
+                let found: Vec<usize> = text.iter().map(|&c| search.step(c)).collect();
+
+                assert_eq!(distances(&query, &text, anywhere), found);
+            }
+        }
+    }
+}
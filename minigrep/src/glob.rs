@@ -0,0 +1,237 @@
+use std::error;
+use std::fmt;
+use std::path::Path;
+
+// Shell style glob patterns used to filter the files visited by a directory walk
+//
+//   *        any run of characters except '/'
+//   **       any run of characters including '/', so '**/test' matches at any depth
+//   ?        any single character except '/'
+//   [a-z]    character classes, negated with '[!a-z]' or '[^a-z]'
+//   \*       escapes the next character
+//
+// A glob without a '/' is matched against the file name only, so '*.rs' matches Rust files in
+// every directory. A glob containing a '/' is matched against the whole path relative to the root
+// of the walk
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub glob: String,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid glob '{}': {}", self.glob, self.message)
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    Star,
+    DoubleStar,
+    // '**/', which matches zero or more whole directories
+    Directories,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    glob: String,
+    tokens: Vec<Token>,
+    match_path: bool,
+}
+
+impl Glob {
+    pub fn new(glob: &str) -> Result<Glob, Error> {
+        let error = |message: &str| Error {
+            glob: glob.to_string(),
+            message: message.to_string(),
+        };
+
+        let match_path = glob.contains('/');
+        // Globs are always matched relative to the root, so a leading '/' only anchors them
+        let pattern = glob.strip_prefix('/').unwrap_or(glob);
+
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '*' => {
+                    if chars.peek() == Some(&'*') {
+                        chars.next();
+                        // '**/' also matches zero directories, so 'a/**/b' matches 'a/b'
+                        match chars.next_if_eq(&'/') {
+                            Some(_) => Token::Directories,
+                            None => Token::DoubleStar,
+                        }
+                    } else {
+                        Token::Star
+                    }
+                }
+                '?' => Token::AnyChar,
+                '\\' => match chars.next() {
+                    Some(escaped) => Token::Char(escaped),
+                    None => return Err(error("trailing backslash")),
+                },
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    let mut first = true;
+
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !first => break,
+                            Some(c) => c,
+                            None => return Err(error("unclosed character class")),
+                        };
+                        first = false;
+
+                        if chars.peek() == Some(&'-') {
+                            chars.next();
+                            match chars.next() {
+                                Some(']') => {
+                                    ranges.push((start, start));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                Some(end) if end >= start => ranges.push((start, end)),
+                                Some(_) => return Err(error("invalid character class range")),
+                                None => return Err(error("unclosed character class")),
+                            }
+                        } else {
+                            ranges.push((start, start));
+                        }
+                    }
+
+                    Token::Class { ranges, negated }
+                }
+                _ => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Glob {
+            glob: glob.to_string(),
+            tokens,
+            match_path,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    // 'path' is relative to the root of the walk
+    pub fn is_match(&self, path: &Path) -> bool {
+        let text = if self.match_path {
+            path.to_string_lossy().replace('\\', "/")
+        } else {
+            match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => return false,
+            }
+        };
+
+        self.matches(&text.chars().collect::<Vec<char>>())
+    }
+
+    // Classic dynamic programming over (token, character) pairs, which avoids the exponential
+    // backtracking a naive recursive matcher hits on globs like '*a*a*a*b'
+    fn matches(&self, text: &[char]) -> bool {
+        // 'current[j]' is true when the tokens seen so far match 'text[..j]'
+        let mut current = vec![false; text.len() + 1];
+        current[0] = true;
+
+        for token in &self.tokens {
+            let mut next = vec![false; text.len() + 1];
+
+            match token {
+                Token::Star | Token::DoubleStar => {
+                    let crosses = *token == Token::DoubleStar;
+                    for j in 0..=text.len() {
+                        next[j] =
+                            current[j] || (j > 0 && next[j - 1] && (crosses || text[j - 1] != '/'));
+                    }
+                }
+                Token::Directories => {
+                    let mut seen = false;
+                    for j in 0..=text.len() {
+                        if j > 0 {
+                            seen |= current[j - 1];
+                        }
+                        next[j] = current[j] || (j > 0 && seen && text[j - 1] == '/');
+                    }
+                }
+                _ => {
+                    for j in 1..=text.len() {
+                        next[j] = current[j - 1] && token_matches(token, text[j - 1]);
+                    }
+                }
+            }
+
+            current = next;
+        }
+
+        current[text.len()]
+    }
+}
+
+fn token_matches(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(expected) => *expected == c,
+        Token::AnyChar => c != '/',
+        Token::Class { ranges, negated } => {
+            c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+        }
+        Token::Star | Token::DoubleStar | Token::Directories => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(glob: &str, path: &str) -> bool {
+        Glob::new(glob).unwrap().is_match(Path::new(path))
+    }
+
+    #[test]
+    fn file_name_globs() {
+        assert!(is_match("*.rs", "src/lib.rs"));
+        assert!(is_match("*.rs", "main.rs"));
+        assert!(!is_match("*.rs", "src/lib.rs.bak"));
+        assert!(is_match("poem.tx?", "poem.txt"));
+        assert!(!is_match("[a-m]*.txt", "dir/poem.txt"));
+        assert!(is_match("[!a-m]*.txt", "dir/poem.txt"));
+    }
+
+    #[test]
+    fn path_globs() {
+        assert!(is_match("src/*.rs", "src/lib.rs"));
+        assert!(!is_match("src/*.rs", "src/walk/mod.rs"));
+        assert!(is_match("src/**/*.rs", "src/walk/mod.rs"));
+        assert!(is_match("src/**/*.rs", "src/lib.rs"));
+        assert!(is_match("**/target", "a/b/target"));
+        assert!(is_match("/target", "target"));
+        assert!(!is_match("/target", "a/target"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(!is_match("a/**/b", "a/xb"));
+    }
+
+    #[test]
+    fn invalid_globs() {
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("[z-a]").is_err());
+        assert!(Glob::new("abc\\").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::glob::Glob;
+
+// Rules read from '.gitignore' and '.ignore' files, following the gitignore format
+//
+//   # comment    blank lines and lines starting with '#' are skipped
+//   *.log        a pattern without a '/' matches a name in this directory or any below it
+//   /target      a pattern with a leading or inner '/' is anchored to this directory
+//   build/       a trailing '/' only matches directories
+//   !keep.log    a leading '!' includes a path again that an earlier rule ignored
+//   \#file       escapes a leading '#' or '!'
+//
+// The last matching rule wins, and the rules of a directory take precedence over the rules of
+// the directories above it. Within a directory '.ignore' takes precedence over '.gitignore',
+// which takes precedence over '.git/info/exclude'. Like git, a path inside an ignored directory
+// can't be included again, because the walk never enters that directory
+//
+// When the walk starts inside a git repository, the ignore files of the directories above it up
+// to the root of the repository apply as well, so 'minigrep foo src/' skips what the top-level
+// '.gitignore' ignores
+
+// Ignore files read in every directory, from the lowest precedence to the highest
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ignore {
+    // Directory the rules are relative to
+    dir: PathBuf,
+    // Path of 'dir' below the directory the rules were read from, empty unless the rules were
+    // read from a directory above the walk
+    prefix: PathBuf,
+    rules: Vec<Rule>,
+    parent: Option<Arc<Ignore>>,
+}
+
+impl Ignore {
+    pub fn new(dir: &Path, parent: Option<Arc<Ignore>>) -> Ignore {
+        Ignore {
+            dir: dir.to_path_buf(),
+            prefix: PathBuf::new(),
+            rules: Vec::new(),
+            parent,
+        }
+    }
+
+    // Reads the ignore files in 'dir', and '.git/info/exclude' when 'dir' is the root of a git
+    // repository. Ignore files that can't be read are treated as empty, like git does
+    pub fn from_dir(dir: &Path, parent: Option<Arc<Ignore>>) -> Ignore {
+        let mut ignore = Ignore::new(dir, parent);
+        ignore.read_files(dir);
+        ignore
+    }
+
+    // Reads the ignore files of the directories above 'root', from the root of the git repository
+    // 'root' is in down to its parent. Returns None when 'root' isn't inside a repository or
+    // none of those directories has rules
+    pub fn from_parents(root: &Path) -> Option<Arc<Ignore>> {
+        let canonical = fs::canonicalize(root).ok()?;
+        let parents = canonical.ancestors().skip(1).collect::<Vec<_>>();
+        let repository = parents.iter().position(|dir| dir.join(".git").exists())?;
+
+        let mut parent = None;
+        for dir in parents[..=repository].iter().rev() {
+            // The walk names its paths below 'root', so the rules are moved there and the part of
+            // the path between 'dir' and 'root' is put back before matching
+            let mut ignore = Ignore::new(root, parent.clone());
+            ignore.prefix = canonical.strip_prefix(dir).ok()?.to_path_buf();
+            ignore.read_files(dir);
+            if !ignore.is_empty() {
+                parent = Some(Arc::new(ignore));
+            }
+        }
+
+        parent
+    }
+
+    fn read_files(&mut self, dir: &Path) {
+        let exclude = dir.join(".git").join("info").join("exclude");
+        let files = std::iter::once(exclude).chain(IGNORE_FILES.iter().map(|name| dir.join(name)));
+        for file in files {
+            if let Ok(contents) = fs::read_to_string(file) {
+                self.add_rules(&contents);
+            }
+        }
+    }
+
+    // Adds every rule of an ignore file. Rules added later take precedence over earlier ones.
+    // Lines that aren't valid globs are skipped
+    pub fn add_rules(&mut self, contents: &str) {
+        self.rules.extend(contents.lines().filter_map(parse_rule));
+    }
+
+    // Whether this directory has rules of its own
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // 'path' is below the directory of these rules, including the directory prefix
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignore = Some(self);
+
+        while let Some(current) = ignore {
+            if let Ok(relative) = path.strip_prefix(&current.dir) {
+                let relative = current.prefix.join(relative);
+                let rule = current
+                    .rules
+                    .iter()
+                    .rev()
+                    .find(|rule| (is_dir || !rule.dir_only) && rule.glob.is_match(&relative));
+                if let Some(rule) = rule {
+                    return !rule.negated;
+                }
+            }
+            ignore = current.parent.as_deref();
+        }
+
+        false
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    // Trailing spaces are dropped unless the last one is escaped with a backslash
+    let mut pattern = line.trim_end_matches(' ').to_string();
+    if pattern.ends_with('\\') && pattern.len() < line.len() {
+        pattern.push(' ');
+    }
+
+    let negated = pattern.starts_with('!');
+    if negated {
+        pattern.remove(0);
+    }
+
+    let dir_only = pattern.ends_with('/');
+    if dir_only {
+        pattern.pop();
+    }
+
+    if pattern.is_empty() {
+        return None;
+    }
+
+    Glob::new(&pattern).ok().map(|glob| Rule {
+        glob,
+        negated,
+        dir_only,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(dir: &str, contents: &str, parent: Option<Arc<Ignore>>) -> Ignore {
+        let mut ignore = Ignore::new(Path::new(dir), parent);
+        ignore.add_rules(contents);
+        ignore
+    }
+
+    fn is_ignored(ignore: &Ignore, path: &str) -> bool {
+        ignore.is_ignored(Path::new(path), path.ends_with('/'))
+    }
+
+    #[test]
+    fn names_anchors_and_directories() {
+        let ignore = ignore(
+            "repo",
+            "# build output\n/target\n*.log\nbuild/\ndocs/*.html\n\n",
+            None,
+        );
+
+        assert!(is_ignored(&ignore, "repo/target/"));
+        assert!(!is_ignored(&ignore, "repo/src/target/"));
+        assert!(is_ignored(&ignore, "repo/src/debug.log"));
+        assert!(is_ignored(&ignore, "repo/src/build/"));
+        assert!(!is_ignored(&ignore, "repo/src/build"));
+        assert!(is_ignored(&ignore, "repo/docs/index.html"));
+        assert!(!is_ignored(&ignore, "repo/src/docs/index.html"));
+        assert!(!is_ignored(&ignore, "repo/src/lib.rs"));
+    }
+
+    #[test]
+    fn negation_and_escapes() {
+        let ignore = ignore("repo", "*.log\n!keep.log\n\\#notes\n\\!bang\n", None);
+
+        assert!(is_ignored(&ignore, "repo/debug.log"));
+        assert!(!is_ignored(&ignore, "repo/keep.log"));
+        assert!(is_ignored(&ignore, "repo/#notes"));
+        assert!(is_ignored(&ignore, "repo/!bang"));
+    }
+
+    #[test]
+    fn nested_rules_take_precedence() {
+        let parent = Arc::new(ignore("repo", "*.txt\n", None));
+        let child = ignore("repo/poems", "!*.txt\n", Some(parent.clone()));
+
+        assert!(is_ignored(&child, "repo/notes.txt"));
+        assert!(!is_ignored(&child, "repo/poems/poem.txt"));
+        assert!(is_ignored(&parent, "repo/poems/poem.txt"));
+    }
+}
@@ -0,0 +1,559 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::case::fold_str;
+use crate::encoding::{self, Encoding};
+use crate::{Config, Error, OutputMode, Walk, WalkOptions};
+
+// A trigram index of a directory, built by 'minigrep index' and stored in INDEX_FILE at its root.
+// For every file it records the set of three byte sequences it contains, after lowercasing ASCII
+// letters. A file can only contain a query when it contains every trigram of the query, so most
+// files can be skipped without reading them. Files starting with a byte order mark are indexed
+// as the UTF-8 text they are searched as.
+//
+// The index also records the modification time and size of every file. A file that changed or
+// was added since the index was built is always searched, so a stale index only makes searches
+// slower, never wrong. Running 'minigrep index' again only reads the files that changed.
+//
+// The file starts with MAGIC and the format version, followed by the number of files and for
+// every file: its path relative to the root, its modification time as seconds and nanoseconds
+// since the Unix epoch, its size and its sorted trigrams, each stored as the difference to the
+// previous one. Lengths and differences are LEB128 varints, everything else little-endian
+
+pub const INDEX_FILE: &str = ".minigrep-index";
+const MAGIC: &[u8; 8] = b"MGINDEX\0";
+const VERSION: u32 = 2;
+
+// Marks a file whose modification time isn't known, so it is never trusted to be unchanged
+const UNKNOWN: (u64, u32) = (u64::MAX, 0);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Index {
+    files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    modified: (u64, u32),
+    size: u64,
+    trigrams: Vec<u32>,
+}
+
+// What 'Index::build' did, for the summary 'minigrep index' prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Changes {
+    pub files: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl Index {
+    // Reads the index of 'root', None when it has none
+    pub fn open(root: &Path) -> Result<Option<Index>, Error> {
+        let path = root.join(INDEX_FILE);
+        match fs::read(&path) {
+            Ok(bytes) => Index::decode(&bytes)
+                .map(Some)
+                .map_err(|err| with_path(err, &path)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(with_path(err, &path)),
+        }
+    }
+
+    // Indexes every file below 'root', reusing the entries of 'old' for files that didn't change.
+    // Entries that can't be read are passed to 'skipped' and left out, and since the index
+    // doesn't know them they are always searched
+    pub fn build(
+        root: &Path,
+        options: WalkOptions,
+        old: Option<Index>,
+        mut skipped: impl FnMut(Error),
+    ) -> Result<(Index, Changes), Error> {
+        let mut old = old.unwrap_or_default();
+        let mut index = Index::default();
+        let mut changes = Changes::default();
+        let mut trigrams = Trigrams::new();
+
+        for path in Walk::new(root, options) {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => {
+                    skipped(err);
+                    continue;
+                }
+            };
+            let Some(relative) = relative(root, &path) else {
+                continue;
+            };
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    skipped(with_path(err, &path));
+                    continue;
+                }
+            };
+
+            let entry = match old.files.remove(&relative) {
+                Some(entry) if entry.is_current(&metadata) => entry,
+                _ => {
+                    let contents = match fs::read(&path) {
+                        Ok(contents) => contents,
+                        Err(err) => {
+                            skipped(with_path(err, &path));
+                            continue;
+                        }
+                    };
+                    changes.updated += 1;
+                    Entry {
+                        modified: modified(&metadata),
+                        size: metadata.len(),
+                        trigrams: trigrams.of(&text(&contents)),
+                    }
+                }
+            };
+            index.files.insert(relative, entry);
+        }
+
+        changes.files = index.files.len();
+        changes.removed = old.files.len();
+        Ok((index, changes))
+    }
+
+    // Writes the index into the root it was built from. The file is replaced at once, so
+    // searches running meanwhile see either the old or the new index
+    pub fn save(&self, root: &Path) -> Result<(), Error> {
+        let path = root.join(INDEX_FILE);
+        let temp = root.join(format!("{INDEX_FILE}.{}", std::process::id()));
+
+        let result = File::create(&temp)
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                self.encode(&mut out)?;
+                out.into_inner()?.sync_all()
+            })
+            .and_then(|()| fs::rename(&temp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.map_err(|err| with_path(err, &path))
+    }
+
+    // Whether the file at 'path' below 'root' may contain a match of 'query'. Files the index
+    // doesn't know about or that changed since it was built may contain anything
+    pub fn may_match(&self, root: &Path, path: &Path, query: &Query) -> bool {
+        let entry = relative(root, path).and_then(|relative| self.files.get(&relative));
+        let Some(entry) = entry else {
+            return true;
+        };
+        if !fs::metadata(path).is_ok_and(|metadata| entry.is_current(&metadata)) {
+            return true;
+        }
+
+        query.alternatives.iter().any(|trigrams| {
+            trigrams
+                .iter()
+                .all(|trigram| entry.trigrams.binary_search(trigram).is_ok())
+        })
+    }
+
+    fn encode(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        write_varint(out, self.files.len() as u64)?;
+
+        for (path, entry) in &self.files {
+            write_varint(out, path.len() as u64)?;
+            out.write_all(path.as_bytes())?;
+            out.write_all(&entry.modified.0.to_le_bytes())?;
+            out.write_all(&entry.modified.1.to_le_bytes())?;
+            out.write_all(&entry.size.to_le_bytes())?;
+            write_varint(out, entry.trigrams.len() as u64)?;
+            let mut previous = 0;
+            for &trigram in &entry.trigrams {
+                write_varint(out, (trigram - previous) as u64)?;
+                previous = trigram;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Index> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a minigrep index"));
+        }
+        let version = u32::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "index format version {version} isn't supported, rebuild it with 'minigrep index'"
+            )));
+        }
+
+        let mut files = BTreeMap::new();
+        for _ in 0..reader.varint()? {
+            let len = reader.varint()? as usize;
+            let path = String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| invalid("corrupt index"))?;
+            let modified = (
+                u64::from_le_bytes(reader.array()?),
+                u32::from_le_bytes(reader.array()?),
+            );
+            let size = u64::from_le_bytes(reader.array()?);
+
+            let count = reader.varint()? as usize;
+            // Every trigram takes at least a byte, which bounds the allocation for corrupt files
+            let mut trigrams = Vec::with_capacity(count.min(reader.bytes.len()));
+            let mut previous: u32 = 0;
+            for _ in 0..count {
+                let delta =
+                    u32::try_from(reader.varint()?).map_err(|_| invalid("corrupt index"))?;
+                previous = previous
+                    .checked_add(delta)
+                    .ok_or_else(|| invalid("corrupt index"))?;
+                trigrams.push(previous);
+            }
+
+            files.insert(
+                path,
+                Entry {
+                    modified,
+                    size,
+                    trigrams,
+                },
+            );
+        }
+
+        Ok(Index { files })
+    }
+}
+
+impl Entry {
+    fn is_current(&self, metadata: &Metadata) -> bool {
+        self.modified != UNKNOWN
+            && self.modified == modified(metadata)
+            && self.size == metadata.len()
+    }
+}
+
+// The trigrams every candidate file must contain, for each pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    alternatives: Vec<Vec<u32>>,
+}
+
+impl Query {
+    // None when the index can't rule out any file for this search: for regular expressions and
+    // approximate matching, for patterns too short to have a trigram, for searches that have to
+    // look at files without matches as well, and for files read in another encoding
+    pub fn new(config: &Config) -> Option<Query> {
+        let narrows = !config.regex
+            && config.fuzzy.is_none()
+            && !config.invert_match
+            && !config.decompress
+            && !config.multiline
+            && config
+                .encoding
+                .is_none_or(|encoding| encoding == Encoding::Utf8)
+            && matches!(
+                config.output,
+                OutputMode::Lines | OutputMode::FilesWithMatches
+            );
+        if !narrows {
+            return None;
+        }
+
+        // Whether case is ignored also depends on the patterns with '--smart-case', assuming it
+        // is only rules out fewer files
+        let ignore_case = config.ignore_case || config.smart_case;
+        let alternatives = config
+            .patterns
+            .iter()
+            .map(|pattern| query_trigrams(pattern, ignore_case))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Query { alternatives })
+    }
+}
+
+fn query_trigrams(pattern: &str, ignore_case: bool) -> Option<Vec<u32>> {
+    // Invalid UTF-8 in a file is searched as U+FFFD, which isn't in the indexed bytes
+    if pattern.contains('\u{fffd}') {
+        return None;
+    }
+
+    let pattern = match ignore_case {
+        true => fold_str(pattern),
+        false => pattern.to_string(),
+    };
+    let mut trigrams: Vec<u32> = pattern
+        .as_bytes()
+        .windows(3)
+        // Ignoring case, a trigram can only be relied on when no other character folds to the
+        // same one. That rules out non-ASCII letters as well as 'k' and 's', which the Kelvin
+        // sign and the long s fold to
+        .filter(|window| {
+            !ignore_case
+                || window
+                    .iter()
+                    .all(|&b| b.is_ascii() && !matches!(b.to_ascii_lowercase(), b'k' | b's'))
+        })
+        .map(trigram)
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+
+    (!trigrams.is_empty()).then_some(trigrams)
+}
+
+fn trigram(window: &[u8]) -> u32 {
+    window.iter().fold(0, |trigram, &b| {
+        trigram << 8 | b.to_ascii_lowercase() as u32
+    })
+}
+
+// Collects the distinct trigrams of a file, with a bit per possible trigram that is reused for
+// every file so each one only takes time linear in its size
+struct Trigrams {
+    seen: Vec<u64>,
+}
+
+impl Trigrams {
+    fn new() -> Trigrams {
+        Trigrams {
+            seen: vec![0; (1 << 24) / 64],
+        }
+    }
+
+    fn of(&mut self, contents: &[u8]) -> Vec<u32> {
+        let mut trigrams = Vec::new();
+        for window in contents.windows(3) {
+            let trigram = trigram(window);
+            let (word, bit) = (trigram as usize / 64, 1 << (trigram % 64));
+            if self.seen[word] & bit == 0 {
+                self.seen[word] |= bit;
+                trigrams.push(trigram);
+            }
+        }
+
+        for &trigram in &trigrams {
+            self.seen[trigram as usize / 64] = 0;
+        }
+        trigrams.sort_unstable();
+        trigrams
+    }
+}
+
+// The path of 'path' below 'root' with '/' separators, None for the index itself
+fn relative(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    if relative
+        .to_str()
+        .is_some_and(|name| name.starts_with(INDEX_FILE))
+    {
+        return None;
+    }
+
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("/"))
+}
+
+fn modified(metadata: &Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(UNKNOWN, |since| (since.as_secs(), since.subsec_nanos()))
+}
+
+// The bytes of 'contents' as they are searched without '--encoding'
+fn text(contents: &[u8]) -> Cow<'_, [u8]> {
+    match encoding::detect(contents, None) {
+        Some((Encoding::Utf8, bom)) => Cow::Borrowed(&contents[bom..]),
+        Some((encoding, bom)) => Cow::Owned(encoding.decode(&contents[bom..]).into_bytes()),
+        None => Cow::Borrowed(contents),
+    }
+}
+
+fn with_path(err: io::Error, path: &Path) -> Error {
+    Error::from(err).with_path(&path.display().to_string())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("index ends unexpectedly"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("corrupt index"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn config(patterns: &[&str]) -> Config {
+        Config {
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn trigrams_of_queries() {
+        let query = |config: &Config| Query::new(config).map(|query| query.alternatives);
+
+        assert_eq!(
+            Some(vec![vec![trigram(b"fro"), trigram(b"rog")]]),
+            query(&config(&["FROG"]))
+        );
+        // Too short
+        assert_eq!(None, query(&config(&["us", "frog"])));
+        assert_eq!(
+            None,
+            query(&Config {
+                regex: true,
+                ..config(&["frog"])
+            })
+        );
+        assert_eq!(
+            None,
+            query(&Config {
+                invert_match: true,
+                ..config(&["frog"])
+            })
+        );
+        // Trigrams with an 's' are left out
+        assert_eq!(
+            Some(vec![vec![
+                trigram(b"bod"),
+                trigram(b"die"),
+                trigram(b"odi")
+            ]]),
+            query(&Config {
+                ignore_case: true,
+                ..config(&["BODIES"])
+            })
+        );
+    }
+
+    #[test]
+    fn builds_updates_and_narrows() {
+        let dir = TempDir::new("index");
+        let root = dir.0.clone();
+        dir.write("poem.txt", include_str!("../poem.txt"));
+        dir.write("sub/notes.txt", "no frogs in here\n");
+        dir.write("other.txt", "nothing to see\n");
+
+        let (index, changes) =
+            Index::build(&root, WalkOptions::default(), None, |err| panic!("{err}")).unwrap();
+        assert_eq!((3, 3, 0), (changes.files, changes.updated, changes.removed));
+        index.save(&root).unwrap();
+        let index = Index::open(&root).unwrap().unwrap();
+
+        let query = Query::new(&config(&["frog"])).unwrap();
+        assert!(index.may_match(&root, &root.join("poem.txt"), &query));
+        assert!(index.may_match(&root, &root.join("sub/notes.txt"), &query));
+        assert!(!index.may_match(&root, &root.join("other.txt"), &query));
+        // Unknown files are always searched
+        assert!(index.may_match(&root, &root.join("new.txt"), &query));
+
+        // Changing the size is enough to notice the change, whatever the clock resolution
+        fs::write(root.join("other.txt"), "a frog after all\n").unwrap();
+        assert!(index.may_match(&root, &root.join("other.txt"), &query));
+        fs::remove_file(root.join("sub/notes.txt")).unwrap();
+
+        let (index, changes) = Index::build(&root, WalkOptions::default(), Some(index), |err| {
+            panic!("{err}")
+        })
+        .unwrap();
+        assert_eq!((2, 1, 1), (changes.files, changes.updated, changes.removed));
+        index.save(&root).unwrap();
+        assert_eq!(Some(index), Index::open(&root).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_entries_it_cant_read() {
+        let dir = TempDir::new("index-dangling");
+        let root = dir.0.clone();
+        dir.write("poem.txt", include_str!("../poem.txt"));
+        std::os::unix::fs::symlink(root.join("missing.txt"), root.join("dangling.txt")).unwrap();
+
+        let options = WalkOptions {
+            follow_links: true,
+            ..WalkOptions::default()
+        };
+        let mut skipped = Vec::new();
+        let (index, changes) =
+            Index::build(&root, options, None, |err| skipped.push(err.to_string())).unwrap();
+
+        assert_eq!(1, skipped.len());
+        assert!(skipped[0].contains("dangling.txt"), "{}", skipped[0]);
+        assert_eq!(1, changes.files);
+        let query = Query::new(&config(&["frog"])).unwrap();
+        assert!(index.may_match(&root, &root.join("dangling.txt"), &query));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = Vec::new();
+        Index::default().encode(&mut bytes).unwrap();
+        assert_eq!(Ok(Index::default()), Index::decode(&bytes).map_err(|_| ()));
+
+        bytes[MAGIC.len()] = 1;
+        let err = Index::decode(&bytes).unwrap_err();
+        assert_eq!(
+            "index format version 1 isn't supported, rebuild it with 'minigrep index'",
+            err.to_string()
+        );
+        assert!(Index::decode(&bytes[..MAGIC.len() + 2]).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt::Write;
+use std::ops::AddAssign;
+
+use crate::Line;
+
+// With '--json' every event of a search is printed as one JSON object per line (JSON Lines).
+// Every object has a "type" and a "data" member. The schema below is stable: members may be
+// added in later versions, but existing ones won't be renamed, removed or change meaning.
+//
+// "begin", printed before the first selected line of a file:
+//
+//   {"type":"begin","data":{"path":"poem.txt"}}
+//
+// "match" for every selected line and "context" for every context line:
+//
+//   {"type":"match","data":{"path":"poem.txt","line_number":3,"absolute_offset":46,
+//    "text":"Then there's a pair of us - don't tell!",
+//    "submatches":[{"text":"us","start":23,"end":25}]}}
+//
+//   'absolute_offset' is the byte offset of the start of the line in the text that was searched.
+//   That's the file itself unless it had to be converted first: with '--decompress' it's the
+//   offset into the decompressed text, and for a file read in another encoding than UTF-8,
+//   whether from '--encoding' or a byte order mark, the offset into the text decoded to UTF-8.
+//   A byte order mark is never counted. 'start' and 'end' are byte offsets into 'text'. 'text'
+//   never includes the line terminator. Context lines and lines selected with '--invert-match'
+//   have no submatches
+//
+//   With '--multiline' a match can span lines, and every line it touches gets the part of it in
+//   that line as a submatch. These submatches also have the numbers of the first and last line
+//   of the whole match:
+//
+//   {"type":"match","data":{"path":"poem.txt","line_number":7,"absolute_offset":142,
+//    "text":"How public, like a frog","submatches":[{"text":"frog","start":19,"end":23,
+//    "first_line":7,"last_line":8}]}}
+//
+// "end", printed after the last line of a file that had a "begin":
+//
+//   {"type":"end","data":{"path":"poem.txt","stats":{"matched_lines":2,"matches":2}}}
+//
+// "summary", printed once when the whole search is finished:
+//
+//   {"type":"summary","data":{"stats":{"files_searched":1,"files_matched":1,
+//    "matched_lines":2,"matches":2}}}
+//
+// Standard input is reported with the path "(standard input)"
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Stats {
+    pub(crate) files_searched: u64,
+    pub(crate) files_matched: u64,
+    pub(crate) matched_lines: u64,
+    pub(crate) matches: u64,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.files_searched += other.files_searched;
+        self.files_matched += other.files_matched;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+}
+
+pub(crate) fn begin(path: &str) -> String {
+    format!(r#"{{"type":"begin","data":{{"path":{}}}}}"#, string(path))
+}
+
+pub(crate) fn line(path: &str, line: &Line<'_>) -> String {
+    let kind = match line.kind {
+        crate::LineKind::Match => "match",
+        crate::LineKind::Context => "context",
+    };
+
+    let mut submatches = String::new();
+    for (i, &(start, end)) in line.matches.iter().enumerate() {
+        if i > 0 {
+            submatches.push(',');
+        }
+        let _ = write!(
+            submatches,
+            r#"{{"text":{},"start":{start},"end":{end}"#,
+            string(&line.text[start..end])
+        );
+        if let Some((first, last)) = line.blocks.get(i) {
+            let _ = write!(submatches, r#","first_line":{first},"last_line":{last}"#);
+        }
+        submatches.push('}');
+    }
+
+    format!(
+        r#"{{"type":"{kind}","data":{{"path":{},"line_number":{},"absolute_offset":{},"text":{},"submatches":[{submatches}]}}}}"#,
+        string(path),
+        line.number,
+        line.offset,
+        string(line.text)
+    )
+}
+
+pub(crate) fn end(path: &str, stats: &Stats) -> String {
+    format!(
+        r#"{{"type":"end","data":{{"path":{},"stats":{{"matched_lines":{},"matches":{}}}}}}}"#,
+        string(path),
+        stats.matched_lines,
+        stats.matches
+    )
+}
+
+pub(crate) fn summary(stats: &Stats) -> String {
+    format!(
+        r#"{{"type":"summary","data":{{"stats":{{"files_searched":{},"files_matched":{},"matched_lines":{},"matches":{}}}}}}}"#,
+        stats.files_searched, stats.files_matched, stats.matched_lines, stats.matches
+    )
+}
+
+// Quotes and escapes 'text' as a JSON string
+fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(r#""plain""#, string("plain"));
+        assert_eq!(
+            r#""say \"hi\"\\ \t\u001b[0m""#,
+            string("say \"hi\"\\ \t\x1b[0m")
+        );
+        assert_eq!(r#""naïve""#, string("naïve"));
+    }
+}
@@ -1,54 +1,824 @@
+use std::collections::VecDeque;
 use std::env;
-use std::error::Error;
 use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::thread;
 
+mod aho_corasick;
+pub mod args;
+pub mod case;
+pub mod config_file;
+pub mod decompress;
+pub mod encoding;
+pub mod error;
+pub mod fuzzy;
+pub mod glob;
+pub mod ignore;
+pub mod index;
+mod json;
+pub mod literal;
+pub mod multiline;
+mod parallel;
+mod printer;
+pub mod regex;
+mod replace;
+pub mod search;
+#[cfg(test)]
+mod temp_dir;
+pub mod walk;
+
+pub use args::Command;
+pub use error::Error;
+pub use glob::Glob;
+pub use regex::Regex;
+pub use search::{Matcher, Searcher, Sink};
+pub use walk::{Walk, WalkOptions};
+
+use aho_corasick::AhoCorasick;
+use encoding::{Encoder, Encoding};
+use fuzzy::Fuzzy;
+use index::Index;
+use literal::Finder;
+use printer::Printer;
+use regex::Bounds;
+
+#[derive(Debug, Clone, Default)]
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    // A line is selected when it matches any of these
+    pub patterns: Vec<String>,
+    // Files holding more patterns, one per line. 'run' adds them to 'patterns'
+    pub pattern_files: Vec<String>,
+    // Files and directories to search
+    pub paths: Vec<String>,
     pub ignore_case: bool,
+    // Ignore case unless a pattern contains an uppercase letter
+    pub smart_case: bool,
+    // Treat the patterns as regular expressions instead of plain substrings
+    pub regex: bool,
+    // Search the whole input at once so matches can span several lines, see the 'multiline'
+    // module
+    pub multiline: bool,
+    // Select lines containing a substring within this Levenshtein distance of a pattern
+    pub fuzzy: Option<usize>,
+    // Only select matches that are whole words, or that span the whole line. 'line_regexp' wins
+    // when both are set
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    pub line_number: bool,
+    // Select the lines that don't match instead of the ones that do
+    pub invert_match: bool,
+    pub output: OutputMode,
+    // Stop searching a file after this many selected lines
+    pub max_count: Option<u64>,
+    // Globs filtering the files searched inside directories
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub follow_links: bool,
+    // Search the paths ignored by '.gitignore' and '.ignore' files while walking directories
+    pub no_ignore: bool,
+    // Search hidden files and directories while walking directories
+    pub hidden: bool,
+    // Search binary files as if they were text instead of only reporting whether they match
+    pub binary: bool,
+    // Search the decompressed contents of compressed files, see the 'decompress' module
+    pub decompress: bool,
+    // Read every input in this encoding instead of UTF-8, see the 'encoding' module
+    pub encoding: Option<Encoding>,
+    // Number of lines to print before and after every matching line
+    pub before_context: usize,
+    pub after_context: usize,
+    pub color: ColorChoice,
+    // Replace the matches with this text, see the 'replace' module for the references it can
+    // contain. Only previews the changes unless 'in_place' is set
+    pub replace: Option<String>,
+    pub in_place: bool,
+    // Number of files searched at once, 0 uses one thread per CPU core
+    pub threads: usize,
+    // Print the results of every file in the order the inputs were given instead of as soon as
+    // they are done
+    pub ordered: bool,
+    // Whether files are memory-mapped instead of read into a buffer
+    pub mmap: MmapChoice,
+    // Skip the configuration file, only looked at when parsing the command line
+    pub no_config: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    // Print every selected line, with context when requested
+    #[default]
+    Lines,
+    // Print the number of selected lines in each file
+    Count,
+    // Print the names of the files with at least one selected line
+    FilesWithMatches,
+    // Print the names of the files without any selected line
+    FilesWithoutMatch,
+    // Print one JSON object per line describing every event, see the 'json' module
+    Json,
+}
+
+// Whether matches, file names and line numbers are highlighted with ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    // Only when writing to a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+// Mapping a file saves copying it into memory, but setting up the mapping costs more than reading
+// a small file. Only regular files can be mapped, anything else is always read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MmapChoice {
+    // Only files of at least 'MMAP_THRESHOLD' bytes
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
-impl Config {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("Not enough arguments");
+impl MmapChoice {
+    pub fn maps(self, len: u64) -> bool {
+        match self {
+            MmapChoice::Auto => len >= MMAP_THRESHOLD,
+            // Empty files can't be mapped
+            MmapChoice::Always => len > 0,
+            MmapChoice::Never => false,
         }
-        // args[0] contains the name of the binary
-        let query = args[1].clone();
-        let file_path = args[2].clone();
+    }
+}
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    pub before_context: usize,
+    pub after_context: usize,
+    // Stop after this many selected lines. Trailing context after the last one is still emitted
+    pub max_count: Option<u64>,
+    pub invert_match: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Match,
+    Context,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub number: u32,
+    // Byte offset of the start of the line in the input
+    pub offset: u64,
+    pub text: &'a str,
+    pub kind: LineKind,
+    // Byte offsets of every match in 'text'. Only filled in for matching lines
+    pub matches: Vec<(usize, usize)>,
+    // Index of the pattern that produced the first match, None when there are no matches
+    pub pattern: Option<usize>,
+    // With '--multiline', the first and last line of the match each entry of 'matches' is a part
+    // of, see the 'multiline' module. Empty otherwise
+    pub blocks: Vec<(u32, u32)>,
+}
+
+// Events reported by 'search_reader' as soon as they are known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<'a> {
+    // Marks the start of a group of contiguous lines
+    GroupStart,
+    Line(Line<'a>),
+}
+
+// The patterns of a 'Config', compiled into whichever matcher searches for them fastest
+pub(crate) enum Patterns {
+    CaseSensitive(search::Literal),
+    CaseInsensitive(search::CaseInsensitive),
+    Regex(Regex),
+    // Several plain patterns, searched for all at once
+    Literals(AhoCorasick),
+    Regexes(Vec<Regex>),
+    // Approximate matches of each pattern, see '--fuzzy'
+    Fuzzy(Vec<Fuzzy>),
+}
+
+impl Patterns {
+    pub(crate) fn new(config: &Config) -> Result<Patterns, regex::Error> {
+        let patterns = match config.multiline && !config.regex {
+            true => config
+                .patterns
+                .iter()
+                .map(|pattern| multiline::unescape(pattern))
+                .collect(),
+            false => config.patterns.clone(),
+        };
+        let patterns = &patterns;
+        let ignore_case = config.ignore_case
+            || (config.smart_case
+                && !patterns
+                    .iter()
+                    .any(|pattern| has_uppercase(pattern, config.regex)));
+        let bounds = match (config.word_regexp, config.line_regexp) {
+            (_, true) => Bounds::Line,
+            (true, false) => Bounds::Word,
+            (false, false) => Bounds::Any,
+        };
+        let regex = |pattern: &str| Regex::build(pattern, ignore_case, bounds);
+
+        if let Some(max_distance) = config.fuzzy {
+            return Ok(Patterns::Fuzzy(
+                patterns
+                    .iter()
+                    .map(|pattern| Fuzzy::new(pattern, max_distance, ignore_case))
+                    .collect(),
+            ));
+        }
+
+        // A single plain query is searched for directly and only what surrounds each occurrence
+        // is checked. Several of them, or an empty one, are escaped and searched for with the
+        // regular expression engine, which checks what surrounds a match itself
+        let single = matches!(patterns.as_slice(), [pattern] if !pattern.is_empty());
+        if bounds != Bounds::Any && !config.regex && !single {
+            let escaped: Vec<String> = patterns
+                .iter()
+                .map(|pattern| regex::escape(pattern))
+                .collect();
+            return match escaped.as_slice() {
+                [pattern] => Ok(Patterns::Regex(regex(pattern)?)),
+                patterns => Ok(Patterns::Regexes(
+                    patterns
+                        .iter()
+                        .map(|pattern| regex(pattern))
+                        .collect::<Result<_, _>>()?,
+                )),
+            };
+        }
+
+        let matcher = match (patterns.as_slice(), config.regex) {
+            ([pattern], true) => Patterns::Regex(regex(pattern)?),
+            ([pattern], false) if ignore_case => {
+                Patterns::CaseInsensitive(search::CaseInsensitive::bounded(pattern, bounds))
+            }
+            ([pattern], false) => {
+                Patterns::CaseSensitive(search::Literal::bounded(pattern, bounds))
+            }
+            (patterns, true) => Patterns::Regexes(
+                patterns
+                    .iter()
+                    .map(|pattern| regex(pattern))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (patterns, false) => Patterns::Literals(AhoCorasick::new(patterns, ignore_case)),
+        };
+        Ok(matcher)
+    }
+
+    // Byte offsets of every non-overlapping match in 'line', empty when it doesn't match
+    pub(crate) fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        self.find(line).0
+    }
+
+    // Like 'find_all', but also returns the index of the pattern that produced the first match
+    pub(crate) fn find(&self, line: &str) -> (Vec<(usize, usize)>, usize) {
+        let matches = match self {
+            Patterns::CaseSensitive(literal) => literal.find_all(line),
+            Patterns::CaseInsensitive(query) => query.find_all(line),
+            Patterns::Regex(regex) => regex.find_iter(line).collect(),
+            Patterns::Literals(literals) => {
+                let matches = literals.find_all(line);
+                let pattern = matches.first().map_or(0, |m| m.2);
+                return (matches.into_iter().map(|m| (m.0, m.1)).collect(), pattern);
+            }
+            Patterns::Regexes(regexes) => return find_regexes(regexes, line),
+            Patterns::Fuzzy(patterns) => return find_fuzzy(patterns, line),
+        };
+        (matches, 0)
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path).expect("Failed to read file");
+// The matches of every pattern from left to right, skipping those overlapping an earlier one.
+// Where several start at the same position the closest and then the longest one is kept
+fn find_fuzzy(patterns: &[Fuzzy], line: &str) -> (Vec<(usize, usize)>, usize) {
+    let mut found: Vec<(fuzzy::Match, usize)> = patterns
+        .iter()
+        .enumerate()
+        .flat_map(|(i, pattern)| pattern.find_all(line).into_iter().map(move |m| (m, i)))
+        .collect();
+    found.sort_by_key(|&(m, i)| (m.start, m.distance, std::cmp::Reverse(m.end), i));
+
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    let mut first = 0;
+    for (m, i) in found {
+        if matches.last().is_some_and(|&(_, end)| m.start < end) {
+            continue;
+        }
+        if matches.is_empty() {
+            first = i;
+        }
+        matches.push((m.start, m.end));
+    }
+    (matches, first)
+}
+
+// The leftmost match of any of 'regexes', preferring the longest and then the first regex when
+// several start at the same position
+fn find_regexes(regexes: &[Regex], line: &str) -> (Vec<(usize, usize)>, usize) {
+    let mut matches = Vec::new();
+    let mut first = 0;
+    let mut pos = 0;
+
+    while pos <= line.len() {
+        let found = regexes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, regex)| regex.find_at(line, pos).map(|(start, end)| (start, end, i)))
+            .min_by_key(|&(start, end, i)| (start, std::cmp::Reverse(end), i));
+        let Some((start, end, pattern)) = found else {
+            break;
+        };
 
-    let results = match config.ignore_case {
-        true => search_case_insensitive(&config.query, &contents),
-        false => search_case_sensitive(&config.query, &contents),
+        if matches.is_empty() {
+            first = pattern;
+        }
+        matches.push((start, end));
+        // Step over empty matches so the same position isn't matched again forever
+        pos = match start == end {
+            true => end + line[end..].chars().next().map_or(1, char::len_utf8),
+            false => end,
+        };
+    }
+
+    (matches, first)
+}
+
+// Escapes like '\W' in a regular expression aren't letters of the query, so they don't count
+fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+// Outcome of a search, mapped to the same exit codes grep uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Match,
+    NoMatch,
+    // At least one input couldn't be searched
+    Error,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Match => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
+
+// Something to search: a file, or standard input when the path is '-'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Input {
+    Stdin,
+    File(String),
+}
+
+// Errors in the query or the options stop the search before it starts. Errors reading a single
+// input are reported on stderr and the remaining inputs are still searched
+pub fn run(config: Config) -> Result<Status, Error> {
+    let config = read_pattern_files(config)?;
+    let matcher = Patterns::new(&config)?;
+    let options = WalkOptions {
+        include: compile_globs(&config.include)?,
+        exclude: compile_globs(&config.exclude)?,
+        follow_links: config.follow_links,
+        no_ignore: config.no_ignore,
+        hidden: config.hidden,
+    };
+
+    // File names are only printed when more than one file can be searched
+    let many_inputs =
+        config.paths.len() > 1 || config.paths.iter().any(|path| Path::new(path).is_dir());
+    let stdout = io::stdout();
+    let color = match config.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        // See https://no-color.org
+        ColorChoice::Auto => {
+            stdout.is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    };
+    // JSON is UTF-8 by definition
+    let out = match stdout.is_terminal() && config.output != OutputMode::Json {
+        true => Encoder::new(stdout.lock(), encoding::terminal()),
+        false => Encoder::new(stdout.lock(), Encoding::Utf8),
+    };
+    let mut printer = Printer::new(&config, out, many_inputs, color);
+
+    let query = index::Query::new(&config);
+    let inputs = config.paths.iter().flat_map(|path| {
+        let inputs: Box<dyn Iterator<Item = Result<Input, Error>> + Send> = if path == "-" {
+            Box::new(std::iter::once(Ok(Input::Stdin)))
+        } else if !Path::new(path).is_dir() {
+            Box::new(std::iter::once(Ok(Input::File(path.clone()))))
+        } else {
+            let root = PathBuf::from(path);
+            let index = open_index(&root, query.is_some());
+            let query = query.clone();
+            Box::new(
+                Walk::new(&root, options.clone())
+                    .filter(move |entry| match (entry, &index, &query) {
+                        (Ok(file), Some(index), Some(query)) => index.may_match(&root, file, query),
+                        (Ok(file), _, _) => !is_index_file(&root, file),
+                        (Err(_), _, _) => true,
+                    })
+                    .map(|entry| Ok(Input::File(entry?.display().to_string()))),
+            )
+        };
+        inputs
+    });
+
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
     };
+    let mut outcome = Outcome::default();
 
-    for line in results {
-        println!("{}: {}", line.0, line.1);
+    // A single file gains nothing from more threads, and searching it directly keeps its output
+    // streaming instead of buffering it until the file is done
+    let result = if threads > 1 && many_inputs {
+        parallel::search(
+            inputs,
+            threads,
+            config.ordered,
+            &matcher,
+            &mut printer,
+            |result| outcome.record(result),
+        )
+    } else {
+        inputs
+            .map(|input| input.and_then(|input| printer.search_input(&matcher, &input)))
+            .try_for_each(|result| outcome.record(result))
+    };
+    let result = result.and_then(|()| printer.finish());
+
+    match result {
+        // Nobody is reading the results anymore, e.g. when piped into 'head'
+        Err(Error::Output(err)) if err.kind() == io::ErrorKind::BrokenPipe => {
+            return Ok(Status::Match)
+        }
+        result => result?,
+    }
+
+    Ok(match (outcome.failed, outcome.matched) {
+        (true, _) => Status::Error,
+        (false, true) => Status::Match,
+        (false, false) => Status::NoMatch,
+    })
+}
+
+// The index of a directory being searched, when there is one and it can narrow the search
+fn open_index(root: &Path, narrows: bool) -> Option<Index> {
+    if !narrows {
+        return None;
+    }
+    match Index::open(root) {
+        Ok(index) => index,
+        Err(err) => {
+            eprintln!("minigrep: {err}, searching without the index");
+            None
+        }
+    }
+}
+
+fn is_index_file(root: &Path, path: &Path) -> bool {
+    path.parent() == Some(root)
+        && path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(index::INDEX_FILE))
+}
+
+// Builds or updates the index of every directory in 'config.paths', see the 'index' module
+pub fn index(config: Config) -> Result<Status, Error> {
+    let options = WalkOptions {
+        include: compile_globs(&config.include)?,
+        exclude: compile_globs(&config.exclude)?,
+        follow_links: config.follow_links,
+        no_ignore: config.no_ignore,
+        hidden: config.hidden,
+    };
+
+    let mut failed = false;
+    for path in &config.paths {
+        let root = Path::new(path);
+        if !root.is_dir() {
+            return Err(Error::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only directories can be indexed",
+            ))
+            .with_path(path));
+        }
+
+        // An index that can't be read is rebuilt from scratch
+        let old = Index::open(root).unwrap_or_else(|err| {
+            eprintln!("minigrep: {err}, rebuilding it");
+            None
+        });
+        let (index, changes) = Index::build(root, options.clone(), old, |err| {
+            eprintln!("minigrep: {err}, leaving it out of the index");
+            failed = true;
+        })?;
+        index.save(root)?;
+        println!(
+            "{path}: indexed {} files, {} added or changed, {} removed",
+            changes.files, changes.updated, changes.removed
+        );
+    }
+
+    match failed {
+        true => Ok(Status::Error),
+        false => Ok(Status::Match),
+    }
+}
+
+// Folds the result of searching every input into the final status
+#[derive(Debug, Default)]
+struct Outcome {
+    matched: bool,
+    failed: bool,
+}
+
+impl Outcome {
+    // Only errors writing the results are returned, as they stop the whole search
+    fn record(&mut self, result: Result<bool, Error>) -> Result<(), Error> {
+        match result {
+            Ok(found) => self.matched |= found,
+            Err(err @ Error::Output(_)) => return Err(err),
+            Err(err) => {
+                eprintln!("minigrep: {err}");
+                self.failed = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Adds the patterns of every pattern file, one per line
+fn read_pattern_files(mut config: Config) -> Result<Config, Error> {
+    for file in std::mem::take(&mut config.pattern_files) {
+        let contents =
+            fs::read_to_string(&file).map_err(|err| Error::from(err).with_path(&file))?;
+        config.patterns.extend(contents.lines().map(String::from));
+    }
+    Ok(config)
+}
+
+fn compile_globs(globs: &[String]) -> Result<Vec<Glob>, glob::Error> {
+    globs.iter().map(|glob| Glob::new(glob)).collect()
+}
+
+// Binary files are recognized by a NUL byte in the first block of the input, like grep does.
+// Nothing is consumed from 'reader'
+pub fn is_binary(reader: &mut impl BufRead) -> io::Result<bool> {
+    // A mapped file is buffered as a whole, only look at as much as a 'BufReader' would hold
+    let buffer = reader.fill_buf()?;
+    Ok(buffer[..buffer.len().min(BINARY_PEEK)].contains(&0))
+}
+
+const BINARY_PEEK: usize = 8 * 1024;
+
+// Searches 'reader' one line at a time, passing every selected line and its context to 'emit'
+// as soon as they are known. Only the last 'before' lines are kept in memory, so arbitrarily
+// large inputs and pipes can be searched. 'find_matches' returns the matches in a line and the
+// index of the pattern that produced the first one
+pub fn search_reader<R: BufRead>(
+    reader: R,
+    find_matches: impl Fn(&str) -> (Vec<(usize, usize)>, usize),
+    options: &SearchOptions,
+    emit: impl FnMut(Event<'_>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    search_lines(reader, |_, line| find_matches(line), |_| 0, options, emit)
+}
+
+// Like 'search_reader', but 'find_matches' is also told the number of the line, for matches
+// that were found before the input was split into lines. 'skip' is given the reader's buffer
+// and returns how many bytes at its start can't hold a match, see 'Matcher::skip'
+pub(crate) fn search_lines<R: BufRead>(
+    mut reader: R,
+    find_matches: impl Fn(u32, &str) -> (Vec<(usize, usize)>, usize),
+    skip: impl Fn(&[u8]) -> usize,
+    options: &SearchOptions,
+    mut emit: impl FnMut(Event<'_>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let (before, after) = (options.before_context, options.after_context);
+    let mut buffer = Vec::new();
+    // The window grows as lines come in, 'before' can be far larger than the input
+    let mut pending: VecDeque<(u32, u64, String)> = VecDeque::new();
+    let mut line_number: u32 = 0;
+    let mut offset: u64 = 0;
+    let mut last_emitted: Option<u32> = None;
+    let mut after_left = 0;
+    let mut selected: u64 = 0;
+    // Matches tend to come in runs, so lines are only skipped after one that didn't match
+    let mut skipping = true;
+
+    loop {
+        let done = options.max_count.is_some_and(|max| selected >= max);
+        if done && after_left == 0 {
+            break;
+        }
+
+        // Lines that would neither be selected nor printed as context are skipped a buffer at a
+        // time, only counting their newlines
+        if skipping && !options.invert_match && before == 0 && after_left == 0 {
+            let available = reader.fill_buf()?;
+            let skipped = skip(available).min(available.len());
+            if let (lines, Some(last)) = literal::count(b'\n', &available[..skipped]) {
+                line_number += lines as u32;
+                offset += last as u64 + 1;
+                reader.consume(last + 1);
+                continue;
+            }
+        }
+
+        // Lines are searched where the reader buffers them, which for a mapped file is the whole
+        // file. Only a line crossing the end of the buffer is copied
+        let line_offset = offset;
+        let available = reader.fill_buf()?;
+        let (bytes, consumed) = match literal::memchr(b'\n', available) {
+            Some(newline) => (&available[..=newline], newline + 1),
+            None => {
+                buffer.clear();
+                if reader.read_until(b'\n', &mut buffer)? == 0 {
+                    break;
+                }
+                (&buffer[..], 0)
+            }
+        };
+        offset += bytes.len() as u64;
+        line_number += 1;
+        // Invalid UTF-8 is replaced with U+FFFD so files with a few stray bytes can still be
+        // searched
+        let line = String::from_utf8_lossy(bytes);
+        let text = trim_line_ending(&line);
+
+        let (matches, pattern) = match done {
+            true => (Vec::new(), 0),
+            false => find_matches(line_number, text),
+        };
+        let pattern = (!matches.is_empty()).then_some(pattern);
+        skipping = matches.is_empty();
+
+        if !done && matches.is_empty() == options.invert_match {
+            let first = pending.front().map_or(line_number, |line| line.0);
+            if last_emitted.is_none_or(|last| first > last + 1) {
+                emit(Event::GroupStart)?;
+            }
+
+            for (number, offset, text) in pending.drain(..) {
+                emit(Event::Line(Line {
+                    number,
+                    offset,
+                    text: &text,
+                    kind: LineKind::Context,
+                    matches: Vec::new(),
+                    pattern: None,
+                    blocks: Vec::new(),
+                }))?;
+            }
+            // Inverted matches are the lines without any match, so there is nothing to highlight
+            emit(Event::Line(Line {
+                number: line_number,
+                offset: line_offset,
+                text,
+                kind: LineKind::Match,
+                matches,
+                pattern,
+                blocks: Vec::new(),
+            }))?;
+
+            last_emitted = Some(line_number);
+            after_left = after;
+            selected += 1;
+        } else if after_left > 0 {
+            emit(Event::Line(Line {
+                number: line_number,
+                offset: line_offset,
+                text,
+                kind: LineKind::Context,
+                matches: Vec::new(),
+                pattern: None,
+                blocks: Vec::new(),
+            }))?;
+
+            last_emitted = Some(line_number);
+            after_left -= 1;
+        } else if before > 0 {
+            // Reuse the allocation of the oldest line once the window is full
+            let mut line = match pending.len() == before {
+                true => pending.pop_front().unwrap().2,
+                false => String::new(),
+            };
+            line.clear();
+            line.push_str(text);
+            pending.push_back((line_number, line_offset, line));
+        }
+
+        reader.consume(consumed);
     }
 
     Ok(())
 }
 
+// Strips a trailing '\n' or '\r\n', matching what 'str::lines' does
+fn trim_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+// The whole of 'contents' is searched at once and line boundaries are only looked up around the
+// matches, which is a lot faster than testing every line on its own
 pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'a str)> {
+    let finder = Finder::new(query.as_bytes());
+    let bytes = contents.as_bytes();
+
+    matching_lines(contents, |start| {
+        finder
+            .find_at(bytes, start)
+            .map(|found| (found, found + query.len()))
+    })
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'a str)> {
+    let query = case::fold_str(query);
+
+    matching_lines(contents, |start| case::find_at(&query, contents, start))
+}
+
+// Collects the lines of 'contents' holding a match, one entry per line, as 'str::lines' would
+// split them. 'find' returns the first match at or after a byte offset. Newlines are only
+// counted up to the lines that are returned
+fn matching_lines<'a>(
+    contents: &'a str,
+    find: impl Fn(usize) -> Option<(usize, usize)>,
+) -> Vec<(u32, &'a str)> {
+    let bytes = contents.as_bytes();
+    let mut results: Vec<(u32, &'a str)> = Vec::new();
+    // Number and start of the line holding the last match, the newlines before it are counted
+    let mut line_number: u32 = 1;
+    let mut line_start = 0;
+    let mut pos = 0;
+
+    while let Some((start, end)) = find(pos) {
+        // 'str::lines' never returns the empty line after a trailing newline
+        if start == bytes.len() && (start == 0 || bytes[start - 1] == b'\n') {
+            break;
+        }
+
+        let (newlines, last) = literal::count(b'\n', &bytes[line_start..start]);
+        line_number += newlines as u32;
+        line_start = last.map_or(line_start, |last| line_start + last + 1);
+
+        let newline = literal::memchr(b'\n', &bytes[start..]).map(|i| start + i);
+        let line = match newline {
+            Some(newline) => trim_line_ending(&contents[line_start..=newline]),
+            None => &contents[line_start..],
+        };
+
+        // A match running into the line ending isn't part of the line, so look again one
+        // character further
+        if end > line_start + line.len() {
+            pos = start + contents[start..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        results.push((line_number, line));
+
+        match newline {
+            Some(newline) => pos = newline + 1,
+            None => break,
+        }
+    }
+
+    results
+}
+
+pub fn search_regex<'a>(regex: &Regex, contents: &'a str) -> Vec<(u32, &'a str)> {
     let mut results: Vec<(u32, &'a str)> = Vec::new();
     let mut line_number: u32 = 0;
 
     for line in contents.lines() {
         line_number += 1;
-        if line.contains(query) {
+        if regex.is_match(line) {
             results.push((line_number, line));
         }
     }
@@ -56,23 +826,110 @@ pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'
     results
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<(u32, &'a str)> {
-    let mut results: Vec<(u32, &'a str)> = Vec::new();
+// Lines containing any of 'patterns', with the index of the pattern found first in each line.
+// All patterns are searched for at once, so thousands of them are as fast as a few
+pub fn search_patterns<'a>(patterns: &[&str], contents: &'a str) -> Vec<(u32, usize, &'a str)> {
+    let patterns = AhoCorasick::new(patterns, false);
+    let mut results: Vec<(u32, usize, &'a str)> = Vec::new();
     let mut line_number: u32 = 0;
 
-    let query = query.to_lowercase();
+    for line in contents.lines() {
+        line_number += 1;
+        if let Some(&(_, _, pattern)) = patterns.find_all(line).first() {
+            results.push((line_number, pattern, line));
+        }
+    }
+
+    results
+}
+
+// Lines containing a substring within Levenshtein distance 'max_distance' of 'query', with every
+// such substring and its distance
+pub fn search_fuzzy<'a>(
+    query: &str,
+    max_distance: usize,
+    contents: &'a str,
+) -> Vec<(u32, &'a str, Vec<fuzzy::Match>)> {
+    let query = Fuzzy::new(query, max_distance, false);
+    let mut results: Vec<(u32, &'a str, Vec<fuzzy::Match>)> = Vec::new();
+    let mut line_number: u32 = 0;
 
     for line in contents.lines() {
         line_number += 1;
-        if line.to_lowercase().contains(&query) {
-            results.push((line_number, line));
+        let matches = query.find_all(line);
+        if !matches.is_empty() {
+            results.push((line_number, line, matches));
         }
     }
 
     results
 }
 
+// Blocks of whole lines touched by a match of 'query', which may contain line breaks written as
+// '\n', with the numbers of their first and last line
+pub fn search_multiline<'a>(query: &str, contents: &'a str) -> Vec<(u32, u32, &'a str)> {
+    let matcher = search::Literal::new(&multiline::unescape(query));
+    let bytes = contents.as_bytes();
+
+    multiline::blocks(contents, &matcher.find_all(contents))
+        .into_iter()
+        .map(|block| {
+            let start = literal::memrchr(b'\n', &bytes[..block.start]).map_or(0, |i| i + 1);
+            let last = block.end.saturating_sub(1).max(block.start);
+            let end = literal::memchr(b'\n', &bytes[last..]).map_or(bytes.len(), |i| last + i);
+            (block.first_line, block.last_line, &contents[start..end])
+        })
+        .collect()
+}
+
+// Expands matching lines into groups of contiguous lines with up to 'before' and 'after' lines
+// of context around each match. Windows that overlap or touch are merged into a single group.
+// The returned lines don't carry match offsets, use 'search_reader' for those
+pub fn with_context<'a>(
+    results: &[(u32, &str)],
+    contents: &'a str,
+    before: usize,
+    after: usize,
+) -> Vec<Vec<Line<'a>>> {
+    let lines: Vec<&'a str> = contents.lines().collect();
+    let mut groups: Vec<Vec<Line<'a>>> = Vec::new();
+    // Index one past the last line added to the current group
+    let mut group_end = 0;
+
+    for &(number, _) in results {
+        let index = number as usize - 1;
+        let start = index.saturating_sub(before).max(group_end);
+        let end = (index + after + 1).min(lines.len());
+
+        if groups.is_empty() || start > group_end {
+            groups.push(Vec::new());
+        }
+        let group = groups.last_mut().unwrap();
+
+        for (i, text) in lines.iter().enumerate().take(end).skip(start) {
+            group.push(Line {
+                number: i as u32 + 1,
+                offset: (text.as_ptr() as usize - contents.as_ptr() as usize) as u64,
+                text,
+                kind: LineKind::Context,
+                matches: Vec::new(),
+                pattern: None,
+                blocks: Vec::new(),
+            });
+        }
+        group_end = group_end.max(end);
+
+        // The match itself may already have been added as context of the previous match
+        if let Some(line) = group.iter_mut().find(|line| line.number == number) {
+            line.kind = LineKind::Match;
+        }
+    }
+
+    groups
+}
+
 #[cfg(test)]
+#[allow(clippy::vec_init_then_push)]
 mod tests {
     use super::*;
 
@@ -106,4 +963,543 @@ Trust me.";
 
         assert_eq!(result, search_case_insensitive(query, contents));
     }
+
+    #[test]
+    fn multiple_patterns() {
+        let patterns = ["frog", "public", "Nobody"];
+        let contents = include_str!("../poem.txt");
+
+        let result = vec![
+            (1, 2, "I'm nobody! Who are you?"),
+            (2, 2, "Are you nobody, too?"),
+            (7, 1, "How public, like a frog"),
+        ];
+
+        assert_eq!(
+            result,
+            search_patterns(&["frog", "public", "nobody"], contents)
+        );
+
+        let config = Config {
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            ignore_case: true,
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+
+        assert_eq!(
+            (vec![(4, 10), (19, 23)], 1),
+            matcher.find("How public, like a frog")
+        );
+        assert_eq!((vec![(4, 10)], 2), matcher.find("I'm nobody!"));
+
+        let config = Config {
+            regex: true,
+            patterns: vec!["fr.g".to_string(), r"\bp\w+".to_string()],
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+
+        assert_eq!(
+            (vec![(4, 10), (19, 23)], 1),
+            matcher.find("How public, like a frog")
+        );
+    }
+
+    #[test]
+    fn whole_buffer_search_finds_the_same_lines() {
+        let contents = "one\r\ntwo one\n\nthree\r\nfour\r";
+
+        for query in ["one", "e", "o", "\r", "e\r", "\n", "r\r", ""] {
+            let naive: Vec<(u32, &str)> = contents
+                .lines()
+                .zip(1..)
+                .filter(|(line, _)| line.contains(query))
+                .map(|(line, number)| (number, line))
+                .collect();
+
+            assert_eq!(naive, search_case_sensitive(query, contents), "{query:?}");
+        }
+
+        assert_eq!(
+            vec![(1, "one"), (2, "two one")],
+            search_case_insensitive("ONE", "one\r\ntwo one\n")
+        );
+    }
+
+    #[test]
+    fn regex_character_classes() {
+        let regex = Regex::new(r"fn\s+\w+").unwrap();
+        let contents = "\
+use std::fs;
+pub fn run(config: Config) {
+fn
+    fn  search() {}";
+
+        let result = vec![
+            (2, "pub fn run(config: Config) {"),
+            (4, "    fn  search() {}"),
+        ];
+
+        assert_eq!(result, search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn regex_anchors() {
+        let regex = Regex::new("^ERROR").unwrap();
+        let contents = "\
+ERROR: disk full
+WARN: no ERROR here
+ERROR";
+
+        let result = vec![(1, "ERROR: disk full"), (3, "ERROR")];
+
+        assert_eq!(result, search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn regex_alternation_and_repetition() {
+        let regex = Regex::new("^(Pick|Duct) (t[a-z]{2,}e)+[.]$").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+Duct tapes.";
+
+        let result = vec![(3, "Pick three."), (4, "Duct tape.")];
+
+        assert_eq!(result, search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let regex = Regex::new_case_insensitive(r"\btr?ust\b").unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+TRUST me.
+Trusty.";
+
+        let result = vec![(3, "TRUST me.")];
+
+        assert_eq!(result, search_regex(&regex, contents));
+    }
+
+    #[test]
+    fn regex_match_bounds() {
+        let regex = Regex::new("a+?b|a+").unwrap();
+
+        assert_eq!(Some((2, 5)), regex.find("xxaab"));
+        assert_eq!(Some((1, 3)), Regex::new("b+").unwrap().find("abbc"));
+        assert_eq!(None, Regex::new("^b").unwrap().find("abbc"));
+        assert_eq!(Some((4, 5)), Regex::new("^b").unwrap().find("abc\nb"));
+    }
+
+    #[test]
+    fn regex_invalid_pattern() {
+        assert!(Regex::new("(unclosed").is_err());
+        assert!(Regex::new("[a-").is_err());
+        assert!(Regex::new("*nothing").is_err());
+        assert!(Regex::new(r"\q").is_err());
+        assert!(Regex::new("fn main() {").is_ok());
+    }
+
+    #[test]
+    fn regex_repetition_limits() {
+        assert!(Regex::new("(?:){4000000000}").is_err());
+        assert!(Regex::new("a{1,100000}").is_err());
+        assert_eq!(Some((0, 0)), Regex::new("(?:){1000}").unwrap().find("abc"));
+        assert_eq!(
+            Some((0, 3)),
+            Regex::new("a(?:){2,}b{1,1000}").unwrap().find("abb")
+        );
+    }
+
+    fn context_line(number: u32, offset: u64, text: &str) -> Line<'_> {
+        Line {
+            number,
+            offset,
+            text,
+            kind: LineKind::Context,
+            matches: Vec::new(),
+            pattern: None,
+            blocks: Vec::new(),
+        }
+    }
+
+    fn match_line(number: u32, offset: u64, text: &str) -> Line<'_> {
+        Line {
+            number,
+            offset,
+            text,
+            kind: LineKind::Match,
+            matches: Vec::new(),
+            pattern: None,
+            blocks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn context_lines() {
+        let contents = "\
+one
+two
+three
+four
+five
+six
+seven
+eight";
+        let results = search_case_sensitive("e", contents);
+
+        let result = vec![vec![
+            match_line(1, 0, "one"),
+            context_line(2, 4, "two"),
+            match_line(3, 8, "three"),
+            context_line(4, 14, "four"),
+            match_line(5, 19, "five"),
+            context_line(6, 24, "six"),
+        ]];
+        assert_eq!(result, with_context(&results[..3], contents, 1, 1));
+
+        let results = search_case_sensitive("t", contents);
+        let result = vec![
+            vec![
+                context_line(1, 0, "one"),
+                match_line(2, 4, "two"),
+                match_line(3, 8, "three"),
+            ],
+            vec![context_line(7, 28, "seven"), match_line(8, 34, "eight")],
+        ];
+        assert_eq!(result, with_context(&results, contents, 1, 0));
+    }
+
+    #[test]
+    fn context_adjacent_windows_merge() {
+        let contents = "\
+match
+a
+b
+match";
+        let results = search_case_sensitive("match", contents);
+
+        let result = vec![vec![
+            match_line(1, 0, "match"),
+            context_line(2, 6, "a"),
+            context_line(3, 8, "b"),
+            match_line(4, 10, "match"),
+        ]];
+
+        assert_eq!(result, with_context(&results, contents, 0, 2));
+        assert_eq!(2, with_context(&results, contents, 0, 1).len());
+    }
+
+    fn find_char(c: char) -> impl Fn(&str) -> (Vec<(usize, usize)>, usize) {
+        move |line: &str| {
+            let matches = line
+                .match_indices(c)
+                .map(|(start, _)| (start, start + c.len_utf8()))
+                .collect();
+            (matches, 0)
+        }
+    }
+
+    #[test]
+    fn streaming_search() {
+        let contents = "\
+one
+two\r
+three
+four
+five
+six
+seven
+eight";
+        let options = SearchOptions {
+            before_context: 1,
+            after_context: 1,
+            ..SearchOptions::default()
+        };
+        let mut events = Vec::new();
+        search_reader(contents.as_bytes(), find_char('v'), &options, |event| {
+            events.push(match event {
+                Event::GroupStart => None,
+                Event::Line(line) => Some((line.number, line.kind, line.text.to_string())),
+            });
+            Ok(())
+        })
+        .unwrap();
+
+        let groups = with_context(&search_case_sensitive("v", contents), contents, 1, 1);
+        let mut result = Vec::new();
+        for group in groups {
+            result.push(None);
+            for line in group {
+                result.push(Some((
+                    line.number,
+                    line.kind,
+                    line.text.trim_end_matches('\r').to_string(),
+                )));
+            }
+        }
+
+        assert_eq!(result, events);
+    }
+
+    #[test]
+    fn streaming_search_match_offsets() {
+        let contents = "Then there's a pair of us - don't tell!\nThey'd banish us, you know.";
+        let options = SearchOptions::default();
+
+        let mut matches = Vec::new();
+        search_reader(contents.as_bytes(), find_char('u'), &options, |event| {
+            if let Event::Line(line) = event {
+                matches.push(line.matches);
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(vec![vec![(23, 24)], vec![(14, 15), (20, 21)]], matches);
+    }
+
+    #[test]
+    fn streaming_search_invalid_utf8() {
+        let contents: &[u8] = b"one\ntwo\nth\xffree\n";
+
+        let mut lines = Vec::new();
+        search_reader(
+            contents,
+            find_char('r'),
+            &SearchOptions::default(),
+            |event| {
+                if let Event::Line(line) = event {
+                    lines.push((line.number, line.text.to_string(), line.matches));
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(vec![(3, "th\u{fffd}ree".to_string(), vec![(5, 6)])], lines);
+    }
+
+    #[test]
+    fn binary_detection() {
+        let mut text: &[u8] = b"one\ntwo\xff\n";
+        let mut binary: &[u8] = b"\x7fELF\x02\x01\x00\x00one\n";
+
+        assert!(!is_binary(&mut text).unwrap());
+        assert!(is_binary(&mut binary).unwrap());
+        assert_eq!(12, binary.len());
+    }
+
+    #[test]
+    fn streaming_search_max_count() {
+        let contents = "\
+I'm nobody! Who are you?
+Are you nobody, too?
+Then there's a pair of us - don't tell!
+They'd banish us, you know.";
+        let options = SearchOptions {
+            after_context: 1,
+            max_count: Some(1),
+            ..SearchOptions::default()
+        };
+
+        let mut lines = Vec::new();
+        search_reader(contents.as_bytes(), find_char('o'), &options, |event| {
+            if let Event::Line(line) = event {
+                lines.push((line.number, line.kind));
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        // The second line also matches but is only printed as trailing context
+        assert_eq!(vec![(1, LineKind::Match), (2, LineKind::Context)], lines);
+    }
+
+    #[test]
+    fn streaming_search_huge_context() {
+        let options = SearchOptions {
+            before_context: 100_000_000_000,
+            after_context: usize::MAX,
+            ..SearchOptions::default()
+        };
+
+        let mut lines = Vec::new();
+        let poem = include_str!("../poem.txt");
+        search_reader(poem.as_bytes(), find_char('f'), &options, |event| {
+            if let Event::Line(line) = event {
+                lines.push((line.number, line.kind));
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(10, lines.len());
+        assert_eq!((7, LineKind::Match), lines[6]);
+    }
+
+    #[test]
+    fn case_insensitive_offsets() {
+        // 'İ' has no simple case folding, so it neither shifts the offsets nor matches 'i'
+        let line = "İstanbul RUST rust";
+        let matcher = |query: &str| Patterns::CaseInsensitive(search::CaseInsensitive::new(query));
+
+        assert_eq!(vec![(10, 14), (15, 19)], matcher("rust").find_all(line));
+        assert_eq!(vec![(0, 3)], matcher("İs").find_all(line));
+        assert!(matcher("is").find_all(line).is_empty());
+    }
+
+    #[test]
+    fn multiline_search() {
+        let poem = include_str!("../poem.txt");
+
+        assert_eq!(
+            vec![(
+                7,
+                8,
+                "How public, like a frog\nTo tell your name the livelong day"
+            )],
+            search_multiline(r"frog\nTo tell", poem)
+        );
+        assert_eq!(
+            vec![(
+                4,
+                6,
+                "They'd banish us, you know.\n\nHow dreary to be somebody!"
+            )],
+            search_multiline(r"know.\n\nHow", poem)
+        );
+        assert!(search_multiline(r"frog\nTo an", poem).is_empty());
+
+        let config = Config {
+            patterns: vec![r"\?\n\w+".to_string()],
+            regex: true,
+            multiline: true,
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+        let lines = multiline::line_matches(&matcher, poem);
+        assert_eq!(vec![(23, 24)], lines[&1].matches);
+        assert_eq!(vec![(1, 2)], lines[&1].blocks);
+        assert_eq!(vec![(0, 3), (19, 20)], lines[&2].matches);
+        assert_eq!(vec![(1, 2), (2, 3)], lines[&2].blocks);
+        assert_eq!(None, lines.get(&4));
+    }
+
+    #[test]
+    fn fuzzy_search() {
+        let poem = include_str!("../poem.txt");
+        let results = search_fuzzy("somebdy", 1, poem);
+
+        assert_eq!(1, results.len());
+        let (number, line, matches) = &results[0];
+        assert_eq!((6, "How dreary to be somebody!"), (*number, *line));
+        assert_eq!(
+            vec![fuzzy::Match {
+                start: 17,
+                end: 25,
+                distance: 1
+            }],
+            *matches
+        );
+
+        let config = Config {
+            patterns: vec!["frgo".to_string(), "bgo".to_string()],
+            fuzzy: Some(1),
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+        assert_eq!((vec![(19, 22)], 0), matcher.find("How public, like a frog"));
+        assert_eq!((vec![(15, 17)], 1), matcher.find("To an admiring bog!"));
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let poem = include_str!("../poem.txt");
+        let lines = |config: Config| -> Vec<usize> {
+            let matcher = Patterns::new(&config).unwrap();
+            poem.lines()
+                .enumerate()
+                .filter(|(_, line)| !matcher.find_all(line).is_empty())
+                .map(|(i, _)| i + 1)
+                .collect()
+        };
+        let config = |patterns: &[&str]| Config {
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            ignore_case: true,
+            word_regexp: true,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            vec![2, 6, 8, 9],
+            lines(Config {
+                word_regexp: false,
+                ..config(&["to"])
+            })
+        );
+        assert_eq!(vec![6, 8, 9], lines(config(&["to"])));
+        assert_eq!(vec![1, 2, 4], lines(config(&["you"])));
+        assert!(lines(config(&["body"])).is_empty());
+        // Neither side of "- don't" is a word character, the characters around it don't matter
+        assert_eq!(vec![3], lines(config(&["- don't"])));
+        assert_eq!(vec![3, 4, 7], lines(config(&["us", "a"])));
+        assert_eq!(
+            vec![6],
+            lines(Config {
+                regex: true,
+                ..config(&["some|somebody"])
+            })
+        );
+
+        let config = |pattern: &str| Config {
+            patterns: vec![pattern.to_string()],
+            line_regexp: true,
+            ..Config::default()
+        };
+        assert_eq!(vec![7], lines(config("How public, like a frog")));
+        assert!(lines(config("How public")).is_empty());
+        assert_eq!(vec![5, 10], lines(config("")));
+        assert_eq!(
+            vec![6, 7],
+            lines(Config {
+                regex: true,
+                ..config("How.*")
+            })
+        );
+
+        let matcher = Patterns::new(&Config {
+            word_regexp: true,
+            line_regexp: false,
+            ..config("us")
+        })
+        .unwrap();
+        assert_eq!(
+            vec![(14, 16)],
+            matcher.find_all("They'd banish us, bus, useful")
+        );
+    }
+
+    #[test]
+    fn smart_case() {
+        let config = |query: &str, regex| Config {
+            patterns: vec![query.to_string()],
+            smart_case: true,
+            regex,
+            ..Config::default()
+        };
+        let matches = |config: &Config| Patterns::new(config).unwrap().find_all("Rust rust");
+
+        assert_eq!(vec![(0, 4), (5, 9)], matches(&config("rust", false)));
+        assert_eq!(vec![(0, 4)], matches(&config("Rust", false)));
+        assert_eq!(
+            vec![(0, 4), (4, 9)],
+            matches(&config("\\Wrust|^rust", true))
+        );
+    }
 }
@@ -0,0 +1,196 @@
+// Fast substring search over whole buffers, used to find candidate matches before anything is
+// split into lines.
+//
+// 'memchr' and 'memrchr' look at a whole machine word at a time, and 'Finder' implements
+// Boyer–Moore–Horspool, which compares the last byte of the needle first and on a mismatch skips
+// ahead by up to the length of the needle
+
+const WORD: usize = std::mem::size_of::<usize>();
+const LOW_BITS: usize = usize::MAX / 255;
+const HIGH_BITS: usize = LOW_BITS << 7;
+// Needles shorter than this are searched for with 'memchr' rather than skipped over
+const SHORT: usize = 8;
+// Bytes in roughly decreasing order of how often they show up in English text and source code
+const COMMON: &[u8] = b" etaoinsrhldcumfpgwyb,.\n\tvk_-()ETAOINSRHLDCUMFPGWYB;:=\"'0123456789";
+
+// Whether any byte of 'word' is zero, see "Bit Twiddling Hacks"
+fn has_zero_byte(word: usize) -> bool {
+    word.wrapping_sub(LOW_BITS) & !word & HIGH_BITS != 0
+}
+
+fn read_word(bytes: &[u8]) -> usize {
+    let mut word = [0; WORD];
+    word.copy_from_slice(&bytes[..WORD]);
+    usize::from_ne_bytes(word)
+}
+
+// Index of the first 'byte' in 'haystack'
+pub fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * byte as usize;
+    let mut i = 0;
+
+    while i + WORD <= haystack.len() {
+        if has_zero_byte(read_word(&haystack[i..]) ^ repeated) {
+            break;
+        }
+        i += WORD;
+    }
+
+    haystack[i..]
+        .iter()
+        .position(|&b| b == byte)
+        .map(|found| i + found)
+}
+
+// Index of the last 'byte' in 'haystack'
+pub fn memrchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LOW_BITS * byte as usize;
+    let mut end = haystack.len();
+
+    while end >= WORD {
+        if has_zero_byte(read_word(&haystack[end - WORD..]) ^ repeated) {
+            break;
+        }
+        end -= WORD;
+    }
+
+    haystack[..end].iter().rposition(|&b| b == byte)
+}
+
+// Index of the first 'first' or 'second' in 'haystack'
+pub fn memchr2(first: u8, second: u8, haystack: &[u8]) -> Option<usize> {
+    let (first_repeated, second_repeated) = (LOW_BITS * first as usize, LOW_BITS * second as usize);
+    let mut i = 0;
+
+    while i + WORD <= haystack.len() {
+        let word = read_word(&haystack[i..]);
+        if has_zero_byte(word ^ first_repeated) || has_zero_byte(word ^ second_repeated) {
+            break;
+        }
+        i += WORD;
+    }
+
+    haystack[i..]
+        .iter()
+        .position(|&b| b == first || b == second)
+        .map(|found| i + found)
+}
+
+// Number of times 'byte' occurs in 'haystack' and the index of the last one. Written so the
+// compiler can vectorize it
+pub fn count(byte: u8, haystack: &[u8]) -> (usize, Option<usize>) {
+    let count = haystack.iter().map(|&b| (b == byte) as usize).sum();
+    let last = match count {
+        0 => None,
+        _ => memrchr(byte, haystack),
+    };
+    (count, last)
+}
+
+#[derive(Debug, Clone)]
+pub struct Finder {
+    needle: Vec<u8>,
+    // How far the needle can move when the byte under its last position is 'skip[byte]'
+    skip: [usize; 256],
+    // Index of the byte of the needle that is likely the least common in text
+    rare: usize,
+}
+
+impl Finder {
+    pub fn new(needle: &[u8]) -> Finder {
+        let mut skip = [needle.len(); 256];
+        if let Some((_, init)) = needle.split_last() {
+            for (i, &b) in init.iter().enumerate() {
+                skip[b as usize] = needle.len() - 1 - i;
+            }
+        }
+
+        // Bytes missing from 'COMMON' are the rarest of all
+        let rank = |b: &u8| COMMON.iter().position(|c| c == b).unwrap_or(COMMON.len());
+        let rare = needle
+            .iter()
+            .enumerate()
+            .max_by_key(|&(i, b)| (rank(b), std::cmp::Reverse(i)))
+            .map_or(0, |(i, _)| i);
+
+        Finder {
+            needle: needle.to_vec(),
+            skip,
+            rare,
+        }
+    }
+
+    // Index of the first occurrence of the needle in 'haystack' at or after 'start'
+    pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        let needle = &self.needle[..];
+        match needle.len() {
+            0 => return (start <= haystack.len()).then_some(start),
+            1 => return memchr(needle[0], haystack.get(start..)?).map(|i| start + i),
+            _ => {}
+        }
+
+        // Short needles skip too little for Boyer–Moore–Horspool to pay off, so candidates are
+        // found by looking for their rarest byte a word at a time instead
+        if needle.len() < SHORT {
+            let rare = self.rare;
+            let mut pos = start;
+            while pos + needle.len() <= haystack.len() {
+                let end = haystack.len() - needle.len() + 1 + rare;
+                pos += memchr(needle[rare], &haystack[pos + rare..end])?;
+                if haystack[pos..pos + needle.len()] == *needle {
+                    return Some(pos);
+                }
+                pos += 1;
+            }
+            return None;
+        }
+
+        let last = needle.len() - 1;
+        let mut pos = start;
+        while pos + needle.len() <= haystack.len() {
+            let byte = haystack[pos + last];
+            if byte == needle[last] && haystack[pos..pos + last] == needle[..last] {
+                return Some(pos);
+            }
+            pos += self.skip[byte as usize];
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_bytes_in_every_position() {
+        let haystack: Vec<u8> = (0..40).collect();
+
+        for byte in 0..40 {
+            assert_eq!(Some(byte as usize), memchr(byte, &haystack));
+            assert_eq!(Some(byte as usize), memrchr(byte, &haystack));
+        }
+        assert_eq!(None, memchr(40, &haystack));
+        assert_eq!(None, memrchr(40, &haystack));
+        assert_eq!(Some(18), memrchr(b'\n', b"one\ntwo\nthree\nfour\n\0\0\0"));
+        assert_eq!((4, Some(18)), count(b'\n', b"one\ntwo\nthree\nfour\n"));
+        assert_eq!((0, None), count(b'\n', b"one"));
+        assert_eq!(Some(1), memchr2(b'e', b'n', b"one"));
+    }
+
+    #[test]
+    fn finds_needles() {
+        let haystack = b"Then there's a pair of us - don't tell! They'd banish us";
+        let finder = Finder::new(b"us");
+
+        assert_eq!(Some(23), finder.find_at(haystack, 0));
+        assert_eq!(Some(54), finder.find_at(haystack, 24));
+        assert_eq!(None, finder.find_at(haystack, 55));
+        assert_eq!(Some(5), Finder::new(b"there").find_at(haystack, 0));
+        assert_eq!(None, Finder::new(b"frog").find_at(haystack, 0));
+        assert_eq!(Some(3), Finder::new(b"").find_at(haystack, 3));
+        assert_eq!(Some(0), Finder::new(b"aab").find_at(b"aabaab", 0));
+        assert_eq!(Some(3), Finder::new(b"aab").find_at(b"aabaab", 1));
+    }
+}
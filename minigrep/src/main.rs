@@ -1,23 +1,43 @@
 use std::env;
 use std::process;
 
-use minigrep::Config;
+use minigrep::args::{self, Command};
+use minigrep::Status;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let config = Config::new(&args).unwrap_or_else(|err| {
-        eprintln!("Failed to parse argmuments: {err}");
-        process::exit(1);
+    let command = Command::parse(&args).unwrap_or_else(|err| {
+        eprintln!("Failed to parse arguments: {err}");
+        eprintln!("{}", args::USAGE);
+        process::exit(Status::Error.exit_code());
     });
 
-    println!(
-        "Searching for \"{}\" in file: {}",
-        config.query, config.file_path
-    );
+    let config = match command {
+        Command::Search(config) => config,
+        Command::Index(config) => match minigrep::index(config) {
+            Ok(status) => process::exit(status.exit_code()),
+            Err(err) => {
+                eprintln!("minigrep: {err}");
+                process::exit(Status::Error.exit_code());
+            }
+        },
+        Command::Help => {
+            print!("{}", args::HELP);
+            return;
+        }
+        Command::Version => {
+            println!("minigrep {}", args::VERSION);
+            return;
+        }
+    };
 
-    if let Err(err) = minigrep::run(config) {
-        eprintln!("Program error occured: {err}");
-        process::exit(1);
+    // Exits with 0 when a line was selected, 1 when none was and 2 when an error occured
+    match minigrep::run(config) {
+        Ok(status) => process::exit(status.exit_code()),
+        Err(err) => {
+            eprintln!("Program error occured: {err}");
+            process::exit(Status::Error.exit_code());
+        }
     }
 }
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use crate::literal;
+use crate::search::Matcher;
+
+// Multi-line searching with '-U'. The whole input is searched at once, so matches can span line
+// breaks, and every line a match touches is selected. The lines of a match make up a block,
+// which is printed like a group of context lines.
+//
+// Plain queries can't contain a newline when they come from the command line, so in this mode
+// '\n' in a plain query stands for a line break, '\t' for a tab and '\\' for a backslash
+
+// Replaces the escapes described above, leaving any other backslash alone
+pub fn unescape(query: &str) -> String {
+    let mut unescaped = String::with_capacity(query.len());
+    let mut chars = query.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+// A match found in the whole input, with the numbers of the first and last line it touches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block {
+    pub first_line: u32,
+    pub last_line: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+// The blocks of 'matches', which are byte offsets in 'contents'. A match ending right after a
+// line break doesn't touch the line after it
+pub fn blocks(contents: &str, matches: &[(usize, usize)]) -> Vec<Block> {
+    let bytes = contents.as_bytes();
+    let mut blocks = Vec::with_capacity(matches.len());
+    let mut line: u32 = 1;
+    let mut counted = 0;
+
+    for &(start, end) in matches {
+        line += literal::count(b'\n', &bytes[counted..start]).0 as u32;
+        let first_line = line;
+        let last = end.saturating_sub(1).max(start);
+        line += literal::count(b'\n', &bytes[start..last]).0 as u32;
+        counted = last;
+
+        blocks.push(Block {
+            first_line,
+            last_line: line,
+            start,
+            end,
+        });
+    }
+
+    blocks
+}
+
+// What 'search_lines' needs to know about a selected line: the matches in it, clipped to the
+// line, the pattern of the first one and the first and last line of the block each match is part
+// of. Lines a match passes over without covering any of their text, like an empty line, get an
+// empty match so they are still selected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LineMatches {
+    pub(crate) matches: Vec<(usize, usize)>,
+    pub(crate) pattern: usize,
+    pub(crate) blocks: Vec<(u32, u32)>,
+}
+
+// The selected lines of 'contents' by number
+pub(crate) fn line_matches(matcher: &impl Matcher, contents: &str) -> HashMap<u32, LineMatches> {
+    let bytes = contents.as_bytes();
+    let matches = matcher.find_all(contents);
+    let mut lines: HashMap<u32, LineMatches> = HashMap::new();
+
+    for block in blocks(contents, &matches) {
+        // Looking again at just the matched text tells which pattern it belongs to
+        let pattern = matcher.find(&contents[block.start..block.end]).1;
+        let mut line_start = literal::memrchr(b'\n', &bytes[..block.start]).map_or(0, |i| i + 1);
+
+        for number in block.first_line..=block.last_line {
+            let line_end = literal::memchr(b'\n', &bytes[line_start..])
+                .map_or(bytes.len(), |i| line_start + i);
+            let text_end = match bytes[line_start..line_end].last() {
+                Some(b'\r') => line_end - 1,
+                _ => line_end,
+            };
+            let start = block.start.clamp(line_start, text_end) - line_start;
+            let end = block.end.clamp(line_start, text_end) - line_start;
+
+            let line = lines.entry(number).or_insert_with(|| LineMatches {
+                pattern,
+                ..LineMatches::default()
+            });
+            line.matches.push((start, end));
+            line.blocks.push((block.first_line, block.last_line));
+            line_start = line_end + 1;
+        }
+    }
+
+    // Searching the whole input finds an empty match once where every line has one, as with an
+    // empty query, so patterns matching the empty string are also tried on every other line
+    if !matcher.find_all("").is_empty() {
+        for (i, text) in contents.lines().enumerate() {
+            let number = i as u32 + 1;
+            let (matches, pattern) = matcher.find(text);
+            if matches.is_empty() || lines.contains_key(&number) {
+                continue;
+            }
+            let blocks = vec![(number, number); matches.len()];
+            lines.insert(
+                number,
+                LineMatches {
+                    matches,
+                    pattern,
+                    blocks,
+                },
+            );
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_queries() {
+        assert_eq!("fn main(\n", unescape(r"fn main(\n"));
+        assert_eq!("a\tb\\n", unescape(r"a\tb\\n"));
+        assert_eq!(r"\d\", unescape(r"\d\"));
+    }
+
+    #[test]
+    fn blocks_span_lines() {
+        let contents = "one\ntwo\nthree\n";
+
+        assert_eq!(
+            vec![
+                Block {
+                    first_line: 1,
+                    last_line: 2,
+                    start: 2,
+                    end: 5
+                },
+                Block {
+                    first_line: 2,
+                    last_line: 2,
+                    start: 6,
+                    end: 8
+                },
+                Block {
+                    first_line: 3,
+                    last_line: 3,
+                    start: 8,
+                    end: 14
+                },
+            ],
+            blocks(contents, &[(2, 5), (6, 8), (8, 14)])
+        );
+    }
+
+    #[test]
+    fn matches_clipped_to_lines() {
+        let matcher = crate::search::Literal::new("e\n\r\nt");
+        let lines = line_matches(&matcher, "one\n\r\ntwo\n");
+
+        let line = |matches: Vec<(usize, usize)>| LineMatches {
+            blocks: vec![(1, 3)],
+            matches,
+            pattern: 0,
+        };
+        assert_eq!(Some(&line(vec![(2, 3)])), lines.get(&1));
+        assert_eq!(Some(&line(vec![(0, 0)])), lines.get(&2));
+        assert_eq!(Some(&line(vec![(0, 1)])), lines.get(&3));
+        assert_eq!(None, lines.get(&4));
+    }
+
+    #[test]
+    fn empty_matches_on_every_line() {
+        let contents = "one\n\nthree\n";
+        let lines = line_matches(&crate::search::Literal::new(""), contents);
+
+        for number in 1..=3 {
+            let line = LineMatches {
+                matches: vec![(0, 0)],
+                pattern: 0,
+                blocks: vec![(number, number)],
+            };
+            assert_eq!(Some(&line), lines.get(&number));
+        }
+        assert_eq!(None, lines.get(&4));
+
+        let lines = line_matches(&crate::Regex::new("^$").unwrap(), contents);
+        let selected: Vec<bool> = (1..=3).map(|number| lines.contains_key(&number)).collect();
+        assert_eq!(vec![false, true, false], selected);
+    }
+}
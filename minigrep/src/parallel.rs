@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::printer::Printer;
+use crate::{Error, Input, Patterns};
+
+// The output of a finished input, or None when the input couldn't even be found
+type Done<'a> = (usize, Option<Printer<'a, Vec<u8>>>, Result<bool, Error>);
+
+// Searches 'inputs' on a pool of 'threads' workers. Every input is searched into a buffer of its
+// own which is written to 'printer' in one piece, so the lines of different files never
+// interleave. With 'ordered' the buffers are written in the order of 'inputs', otherwise as soon
+// as they are done. 'record' is called with the result of every input on the calling thread and
+// stops the search by returning an error
+pub(crate) fn search<'a, W: Write>(
+    inputs: impl Iterator<Item = Result<Input, Error>> + Send,
+    threads: usize,
+    ordered: bool,
+    matcher: &Patterns,
+    printer: &mut Printer<'a, W>,
+    mut record: impl FnMut(Result<bool, Error>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let template = printer.buffer();
+    let stop = AtomicBool::new(false);
+    let (job_sender, jobs) = mpsc::channel::<(usize, Input)>();
+    // Workers take turns waiting for the next job
+    let jobs = Mutex::new(jobs);
+    let (done_sender, done) = mpsc::channel::<Done<'a>>();
+
+    thread::scope(|scope| {
+        let (template, stop, jobs) = (&template, &stop, &jobs);
+
+        // Walking directories happens on its own thread so the workers get their first files
+        // before the walk is finished
+        let sender = done_sender.clone();
+        scope.spawn(move || {
+            for (index, input) in inputs.enumerate() {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let sent = match input {
+                    Ok(input) => job_sender.send((index, input)).is_ok(),
+                    Err(err) => sender.send((index, None, Err(err))).is_ok(),
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..threads {
+            let sender = done_sender.clone();
+            scope.spawn(move || loop {
+                let job = jobs.lock().unwrap().recv();
+                let Ok((index, input)) = job else { break };
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let mut buffer = template.buffer();
+                let result = buffer.search_input(matcher, &input);
+                if sender.send((index, Some(buffer), result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // Otherwise 'done' would never see the last sender go away
+        drop(done_sender);
+
+        let mut write = |buffer: Option<Printer<'a, Vec<u8>>>, result| {
+            if let Some(buffer) = buffer {
+                printer.append(buffer)?;
+            }
+            record(result)
+        };
+
+        // Finished inputs waiting for the ones before them when 'ordered' is set
+        let mut waiting = HashMap::new();
+        let mut next = 0;
+        let result = done.iter().try_for_each(|(index, buffer, result)| {
+            if !ordered {
+                return write(buffer, result);
+            }
+
+            waiting.insert(index, (buffer, result));
+            while let Some((buffer, result)) = waiting.remove(&next) {
+                write(buffer, result)?;
+                next += 1;
+            }
+            Ok(())
+        });
+
+        if result.is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use crate::Config;
+
+    #[test]
+    fn ordered_output_matches_sequential_search() {
+        let temp = TempDir::new("parallel");
+        let dir = &temp.0;
+
+        let mut paths = Vec::new();
+        for i in 0..20 {
+            let path = dir.join(format!("{i}.txt"));
+            let contents = "needle\nhay\n".repeat(i * 50) + "needle in the last line\n";
+            fs::write(&path, contents).unwrap();
+            paths.push(path.display().to_string());
+        }
+        paths.push(dir.join("missing.txt").display().to_string());
+
+        let config = Config {
+            patterns: vec!["needle".to_string()],
+            after_context: 1,
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+        let inputs = || paths.iter().map(|path| Ok(Input::File(path.clone())));
+
+        let mut expected = Vec::new();
+        let mut results = Vec::new();
+        {
+            let mut printer = Printer::new(&config, &mut expected, true, false);
+            for input in inputs() {
+                let result = input.and_then(|input| printer.search_input(&matcher, &input));
+                results.push(result.is_ok());
+            }
+        }
+
+        let mut output = Vec::new();
+        let mut parallel_results = Vec::new();
+        {
+            let mut printer = Printer::new(&config, &mut output, true, false);
+            search(inputs(), 4, true, &matcher, &mut printer, |result| {
+                parallel_results.push(result.is_ok());
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        assert_eq!(results, parallel_results);
+        assert_eq!(String::from_utf8(expected), String::from_utf8(output));
+    }
+}
@@ -0,0 +1,530 @@
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+
+use crate::json::{self, Stats};
+use crate::replace::{self, Replacement};
+use crate::search::{Finish, Searcher, Sink};
+use crate::{Config, Error, Input, Line, LineKind, OutputMode, Patterns, SearchOptions};
+
+// ANSI escape sequences, using the same colors as grep
+const MATCH_COLOR: &str = "\x1b[1;31m";
+const FILE_COLOR: &str = "\x1b[35m";
+const LINE_NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const REMOVED_COLOR: &str = "\x1b[31m";
+const ADDED_COLOR: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+pub(crate) struct Printer<'a, W: Write> {
+    config: &'a Config,
+    out: W,
+    with_file_names: bool,
+    color: bool,
+    printed_group: bool,
+    // Totals over every input, only used for the JSON summary
+    stats: Stats,
+    replacement: Option<Replacement>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    pub(crate) fn new(config: &'a Config, out: W, with_file_names: bool, color: bool) -> Self {
+        Printer {
+            config,
+            out,
+            with_file_names,
+            color,
+            printed_group: false,
+            stats: Stats::default(),
+            replacement: config.replace.as_deref().map(Replacement::new),
+        }
+    }
+
+    // Called once every input was searched
+    pub(crate) fn finish(&mut self) -> Result<(), Error> {
+        if self.config.output == OutputMode::Json {
+            writeln!(self.out, "{}", json::summary(&self.stats)).map_err(Error::Output)?;
+        }
+        self.out.flush().map_err(Error::Output)
+    }
+
+    // A printer with the same settings that writes into memory, so a file can be searched on
+    // another thread and its output written with 'append' once it is done
+    pub(crate) fn buffer(&self) -> Printer<'a, Vec<u8>> {
+        Printer::new(self.config, Vec::new(), self.with_file_names, self.color)
+    }
+
+    pub(crate) fn append(&mut self, buffer: Printer<'a, Vec<u8>>) -> Result<(), Error> {
+        // The buffer didn't know about the groups printed before it
+        if buffer.printed_group && self.printed_group && self.has_context() {
+            self.paint(SEPARATOR_COLOR, "--")?;
+            writeln!(self.out).map_err(Error::Output)?;
+        }
+        self.out.write_all(&buffer.out).map_err(Error::Output)?;
+        self.printed_group |= buffer.printed_group;
+        self.stats += buffer.stats;
+        Ok(())
+    }
+
+    pub(crate) fn search_input(
+        &mut self,
+        matcher: &Patterns,
+        input: &Input,
+    ) -> Result<bool, Error> {
+        let config = self.config;
+        if let (Input::File(path), Some(replacement), true) =
+            (input, &self.replacement, config.in_place)
+        {
+            return replace::in_place(matcher, replacement, path, config.binary)
+                .map_err(|err| err.with_path(path));
+        }
+
+        match input {
+            Input::Stdin => {
+                let name = "(standard input)";
+                self.search(matcher, name, io::stdin().lock())
+                    .map_err(|err| err.with_path(name))
+            }
+            Input::File(path) => self.output(matcher, path, |searcher, output| {
+                searcher.search_path(matcher, path, output)
+            }),
+        }
+    }
+
+    // Returns whether anything was selected: a line, or the file itself when listing the files
+    // without a match
+    pub(crate) fn search(
+        &mut self,
+        matcher: &Patterns,
+        name: &str,
+        reader: impl BufRead,
+    ) -> Result<bool, Error> {
+        self.output(matcher, name, |searcher, output| {
+            searcher.search_reader(matcher, reader, output)
+        })
+    }
+
+    fn output(
+        &mut self,
+        matcher: &Patterns,
+        name: &str,
+        search: impl FnOnce(&Searcher, &mut Output<'_, 'a, W>) -> Result<(), Error>,
+    ) -> Result<bool, Error> {
+        let searcher = self.searcher();
+        let replacement = self.replacement.take();
+        let mut output = Output {
+            printer: self,
+            matcher,
+            replacement: replacement.as_ref(),
+            name,
+            lines: 0,
+            replaced: 0,
+            listed: false,
+        };
+        let result = search(&searcher, &mut output);
+        let listed = output.listed;
+
+        self.replacement = replacement;
+        result.map(|()| listed)
+    }
+
+    // Only printed lines come with context, and one selected line is enough to know whether a
+    // file should be listed. Lines of binary files are still counted and written as JSON
+    fn searcher(&self) -> Searcher {
+        let config = self.config;
+        let mut options = SearchOptions {
+            before_context: config.before_context,
+            after_context: config.after_context,
+            max_count: config.max_count,
+            invert_match: config.invert_match,
+        };
+
+        let printed = config.output == OutputMode::Json
+            || (config.output == OutputMode::Lines && self.replacement.is_none());
+        if !printed {
+            options.before_context = 0;
+            options.after_context = 0;
+        }
+        if matches!(
+            config.output,
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch
+        ) {
+            options.max_count = Some(1);
+        }
+
+        Searcher {
+            options,
+            binary: config.binary || config.output != OutputMode::Lines,
+            multiline: config.multiline,
+            decompress: config.decompress,
+            encoding: config.encoding,
+            mmap: config.mmap,
+        }
+    }
+
+    // Groups that aren't adjacent are separated by '--'
+    fn print_group_start(&mut self) -> Result<(), Error> {
+        if self.has_context() && self.printed_group {
+            self.paint(SEPARATOR_COLOR, "--")?;
+            writeln!(self.out).map_err(Error::Output)?;
+        }
+        self.printed_group = true;
+        Ok(())
+    }
+
+    // Selected lines are printed as 'N: text' and context lines as 'N- text', like grep does,
+    // with the file name in front when there is one
+    fn print(&mut self, file: Option<&str>, line: &Line<'_>) -> Result<(), Error> {
+        let separator = match line.kind {
+            LineKind::Match => ':',
+            LineKind::Context => '-',
+        };
+
+        if let Some(file) = file {
+            self.paint(FILE_COLOR, file)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if self.config.line_number {
+            self.paint(LINE_NUMBER_COLOR, line.number)?;
+            self.paint(SEPARATOR_COLOR, separator)?;
+        }
+        if file.is_some() || self.config.line_number {
+            write!(self.out, " ").map_err(Error::Output)?;
+        }
+
+        let mut printed = 0;
+        for &(start, end) in &line.matches {
+            if start == end {
+                continue;
+            }
+            write!(self.out, "{}", &line.text[printed..start]).map_err(Error::Output)?;
+            self.paint(MATCH_COLOR, &line.text[start..end])?;
+            printed = end;
+        }
+        writeln!(self.out, "{}", &line.text[printed..]).map_err(Error::Output)
+    }
+
+    // Whether groups of lines are separated by '--', which multi-line matches are printed as too
+    fn has_context(&self) -> bool {
+        self.config.before_context > 0 || self.config.after_context > 0 || self.config.multiline
+    }
+
+    fn paint(&mut self, color: &str, text: impl Display) -> Result<(), Error> {
+        let result = match self.color {
+            true => write!(self.out, "{color}{text}{RESET}"),
+            false => write!(self.out, "{text}"),
+        };
+        result.map_err(Error::Output)
+    }
+}
+
+// The sink printing the results of a single input in the format 'config.output' asks for
+struct Output<'p, 'a, W: Write> {
+    printer: &'p mut Printer<'a, W>,
+    matcher: &'p Patterns,
+    replacement: Option<&'p Replacement>,
+    name: &'p str,
+    // Lines written so far, only used for JSON
+    lines: u64,
+    // Lines shown with their replacement, only used with '--replace'
+    replaced: u64,
+    listed: bool,
+}
+
+impl<'p, W: Write> Output<'p, '_, W> {
+    fn json(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        let out = &mut self.printer.out;
+        if self.lines == 0 {
+            writeln!(out, "{}", json::begin(self.name)).map_err(Error::Output)?;
+        }
+        self.lines += 1;
+        writeln!(out, "{}", json::line(self.name, line)).map_err(Error::Output)
+    }
+
+    // Previews the replacements as a unified diff with one hunk per changed line, which 'patch'
+    // can apply as it is
+    fn replace(&mut self, replacement: &Replacement, line: &Line<'_>) -> Result<(), Error> {
+        let Some(replaced) = replace::replace_line(self.matcher, replacement, line.text) else {
+            return Ok(());
+        };
+        let (printer, name) = (&mut *self.printer, self.name);
+
+        if self.replaced == 0 {
+            printer.paint(FILE_COLOR, format_args!("--- {name}"))?;
+            writeln!(printer.out).map_err(Error::Output)?;
+            printer.paint(FILE_COLOR, format_args!("+++ {name}"))?;
+            writeln!(printer.out).map_err(Error::Output)?;
+        }
+        self.replaced += 1;
+
+        let number = line.number;
+        printer.paint(SEPARATOR_COLOR, format_args!("@@ -{number} +{number} @@"))?;
+        writeln!(printer.out).map_err(Error::Output)?;
+        printer.paint(REMOVED_COLOR, format_args!("-{}", line.text))?;
+        writeln!(printer.out).map_err(Error::Output)?;
+        printer.paint(ADDED_COLOR, format_args!("+{replaced}"))?;
+        writeln!(printer.out).map_err(Error::Output)
+    }
+
+    fn file(&self) -> Option<&'p str> {
+        self.printer.with_file_names.then_some(self.name)
+    }
+}
+
+impl<W: Write> Sink for Output<'_, '_, W> {
+    fn matched(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        match (self.printer.config.output, self.replacement) {
+            (OutputMode::Json, _) => self.json(line),
+            (OutputMode::Lines, Some(replacement)) => self.replace(replacement, line),
+            (OutputMode::Lines, None) => self.printer.print(self.file(), line),
+            _ => Ok(()),
+        }
+    }
+
+    fn context(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        match self.printer.config.output {
+            OutputMode::Json => self.json(line),
+            OutputMode::Lines => self.printer.print(self.file(), line),
+            _ => Ok(()),
+        }
+    }
+
+    fn group_start(&mut self) -> Result<(), Error> {
+        match (self.printer.config.output, self.replacement) {
+            (OutputMode::Lines, None) => self.printer.print_group_start(),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, finish: &Finish) -> Result<(), Error> {
+        let (printer, name) = (&mut *self.printer, self.name);
+        let matched = finish.matched_lines > 0;
+
+        self.listed = match printer.config.output {
+            // Printing the lines of a binary file would only fill the terminal with garbage
+            OutputMode::Lines if finish.binary => {
+                if matched {
+                    write!(printer.out, "Binary file ").map_err(Error::Output)?;
+                    printer.paint(FILE_COLOR, name)?;
+                    writeln!(printer.out, " matches").map_err(Error::Output)?;
+                }
+                matched
+            }
+            OutputMode::Lines if self.replacement.is_some() => self.replaced > 0,
+            OutputMode::Lines => matched,
+            OutputMode::Json => {
+                let stats = Stats {
+                    files_searched: 1,
+                    files_matched: matched as u64,
+                    matched_lines: finish.matched_lines,
+                    matches: finish.matches,
+                };
+                if self.lines > 0 {
+                    writeln!(printer.out, "{}", json::end(name, &stats)).map_err(Error::Output)?;
+                }
+                printer.stats += stats;
+                matched
+            }
+            OutputMode::Count => {
+                if printer.with_file_names {
+                    printer.paint(FILE_COLOR, name)?;
+                    printer.paint(SEPARATOR_COLOR, ':')?;
+                    write!(printer.out, " ").map_err(Error::Output)?;
+                }
+                writeln!(printer.out, "{}", finish.matched_lines).map_err(Error::Output)?;
+                matched
+            }
+            OutputMode::FilesWithMatches => matched,
+            OutputMode::FilesWithoutMatch => !matched,
+        };
+
+        let listing = matches!(
+            printer.config.output,
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch
+        );
+        if listing && self.listed {
+            printer.paint(FILE_COLOR, name)?;
+            writeln!(printer.out).map_err(Error::Output)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MmapChoice;
+
+    fn output(config: &Config, color: bool, with_file_names: bool) -> String {
+        let matcher = Patterns::new(config).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(config, &mut out, with_file_names, color);
+        let poem = include_str!("../poem.txt");
+        printer
+            .search(&matcher, "poem.txt", poem.as_bytes())
+            .unwrap();
+        printer.finish().unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn highlights_matches() {
+        let config = Config {
+            patterns: vec!["o".to_string()],
+            line_number: true,
+            max_count: Some(1),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            "\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m I'm n\x1b[1;31mo\x1b[0mb\x1b[1;31mo\x1b[0mdy! Wh\x1b[1;31mo\x1b[0m are y\x1b[1;31mo\x1b[0mu?\n",
+            output(&config, true, false)
+        );
+        assert_eq!(
+            "1: I'm nobody! Who are you?\n",
+            output(&config, false, false)
+        );
+    }
+
+    #[test]
+    fn highlights_file_names() {
+        let config = Config {
+            patterns: vec!["frog".to_string()],
+            output: OutputMode::FilesWithMatches,
+            ..Config::default()
+        };
+
+        assert_eq!("\x1b[35mpoem.txt\x1b[0m\n", output(&config, true, true));
+        assert_eq!("poem.txt\n", output(&config, false, false));
+    }
+
+    #[test]
+    fn binary_files() {
+        let config = Config {
+            patterns: vec!["one".to_string()],
+            ..Config::default()
+        };
+        let matcher = Patterns::new(&config).unwrap();
+        let contents: &[u8] = b"one\x00\ntwo\none\n";
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, &mut out, false, false);
+
+        assert!(printer.search(&matcher, "a.out", contents).unwrap());
+        assert_eq!(
+            "Binary file a.out matches\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let config = Config {
+            binary: true,
+            ..config
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&config, &mut out, false, false);
+
+        assert!(printer.search(&matcher, "a.out", contents).unwrap());
+        assert_eq!("one\0\none\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn mapped_and_read_files_print_the_same() {
+        let search = |mmap| {
+            let config = Config {
+                patterns: vec!["the".to_string()],
+                line_number: true,
+                before_context: 1,
+                mmap,
+                ..Config::default()
+            };
+            let matcher = Patterns::new(&config).unwrap();
+            let mut out = Vec::new();
+            let mut printer = Printer::new(&config, &mut out, false, false);
+            printer
+                .search_input(&matcher, &Input::File("poem.txt".to_string()))
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let mapped = search(MmapChoice::Always);
+        assert_eq!(search(MmapChoice::Never), mapped);
+        assert!(mapped.contains("How public, like a frog"));
+    }
+
+    #[test]
+    fn replacement_preview() {
+        let config = Config {
+            patterns: vec!["(pair of|banish) us".to_string()],
+            regex: true,
+            replace: Some("$1 them".to_string()),
+            ..Config::default()
+        };
+
+        let result = "\
+--- poem.txt
++++ poem.txt
+@@ -3 +3 @@
+-Then there's a pair of us - don't tell!
++Then there's a pair of them - don't tell!
+@@ -4 +4 @@
+-They'd banish us, you know.
++They'd banish them, you know.
+";
+
+        assert_eq!(result, output(&config, false, false));
+    }
+
+    #[test]
+    fn json_lines() {
+        let config = Config {
+            patterns: vec!["us".to_string()],
+            output: OutputMode::Json,
+            after_context: 1,
+            ..Config::default()
+        };
+
+        let result = [
+            r#"{"type":"begin","data":{"path":"poem.txt"}}"#,
+            r#"{"type":"match","data":{"path":"poem.txt","line_number":3,"absolute_offset":46,"text":"Then there's a pair of us - don't tell!","submatches":[{"text":"us","start":23,"end":25}]}}"#,
+            r#"{"type":"match","data":{"path":"poem.txt","line_number":4,"absolute_offset":86,"text":"They'd banish us, you know.","submatches":[{"text":"us","start":14,"end":16}]}}"#,
+            r#"{"type":"context","data":{"path":"poem.txt","line_number":5,"absolute_offset":114,"text":"","submatches":[]}}"#,
+            r#"{"type":"end","data":{"path":"poem.txt","stats":{"matched_lines":2,"matches":2}}}"#,
+            r#"{"type":"summary","data":{"stats":{"files_searched":1,"files_matched":1,"matched_lines":2,"matches":2}}}"#,
+        ];
+
+        assert_eq!(result.join("\n") + "\n", output(&config, true, false));
+    }
+
+    #[test]
+    fn json_multiline_blocks() {
+        let config = Config {
+            patterns: vec![r"frog\nTo".to_string()],
+            output: OutputMode::Json,
+            multiline: true,
+            ..Config::default()
+        };
+
+        let result = [
+            r#"{"type":"begin","data":{"path":"poem.txt"}}"#,
+            r#"{"type":"match","data":{"path":"poem.txt","line_number":7,"absolute_offset":142,"text":"How public, like a frog","submatches":[{"text":"frog","start":19,"end":23,"first_line":7,"last_line":8}]}}"#,
+            r#"{"type":"match","data":{"path":"poem.txt","line_number":8,"absolute_offset":166,"text":"To tell your name the livelong day","submatches":[{"text":"To","start":0,"end":2,"first_line":7,"last_line":8}]}}"#,
+            r#"{"type":"end","data":{"path":"poem.txt","stats":{"matched_lines":2,"matches":2}}}"#,
+            r#"{"type":"summary","data":{"stats":{"files_searched":1,"files_matched":1,"matched_lines":2,"matches":2}}}"#,
+        ];
+
+        assert_eq!(result.join("\n") + "\n", output(&config, false, false));
+    }
+
+    #[test]
+    fn json_lines_without_matches() {
+        let config = Config {
+            patterns: vec!["toad".to_string()],
+            output: OutputMode::Json,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            "{\"type\":\"summary\",\"data\":{\"stats\":{\"files_searched\":1,\"files_matched\":0,\"matched_lines\":0,\"matches\":0}}}\n",
+            output(&config, false, false)
+        );
+    }
+}
@@ -0,0 +1,913 @@
+use std::error;
+use std::fmt;
+
+use crate::case::fold;
+
+// A small regular expression engine. Patterns are parsed into a syntax tree, compiled into a
+// program and executed with a Pike VM, so matching never backtracks and runs in time linear to
+// the length of the searched text
+//
+// Supported syntax:
+//
+//   literals and escapes      a  \.  \\  \n  \t
+//   any character             .  (anything except a newline)
+//   character classes         [abc]  [a-z]  [^0-9]  \d  \D  \w  \W  \s  \S
+//   anchors                   ^  $  \b  \B
+//   groups and alternation    (a|b)  (?:a|b)
+//   repetition                *  +  ?  {n}  {n,}  {n,m}  (append '?' to make it lazy)
+//
+// '^' and '$' match at the start and end of every line, and the class shorthands are Unicode
+// aware: '\w' is any alphanumeric character or '_', '\d' any numeric character and '\s' any
+// whitespace
+
+// Upper bound on the size of a compiled program, mostly to stop counted repetitions such as
+// 'a{1000}{1000}' from exhausting memory
+const MAX_PROGRAM_SIZE: usize = 100_000;
+
+// Largest count allowed in '{n}', '{n,}' or '{n,m}'
+const MAX_REPEAT: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub message: String,
+    // Position of the offending character in the pattern, counted in characters
+    pub position: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for Error {}
+
+// What has to surround a match. 'Word' only accepts matches that aren't part of a longer word,
+// so neither the character before nor the one after may be a word character, and 'Line' only
+// accepts matches spanning a whole line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bounds {
+    #[default]
+    Any,
+    Word,
+    Line,
+}
+
+impl Bounds {
+    // Whether what surrounds the match from 'start' to 'end' in 'text' is what these bounds ask
+    // for, for matchers that find their matches without the regular expression engine
+    pub fn hold(self, text: &str, start: usize, end: usize) -> bool {
+        match self {
+            Bounds::Any => true,
+            Bounds::Word => {
+                Look::NotAfterWord.holds(text, start) && Look::NotBeforeWord.holds(text, end)
+            }
+            Bounds::Line => Look::LineStart.holds(text, start) && Look::LineEnd.holds(text, end),
+        }
+    }
+}
+
+// Escapes every character with a special meaning, so 'text' matches itself
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    slots: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        Regex::build(pattern, false, Bounds::Any)
+    }
+
+    pub fn new_case_insensitive(pattern: &str) -> Result<Regex, Error> {
+        Regex::build(pattern, true, Bounds::Any)
+    }
+
+    // Like 'new', but also choosing whether case is ignored and what has to surround a match
+    pub fn build(pattern: &str, ignore_case: bool, bounds: Bounds) -> Result<Regex, Error> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse()?;
+        let node = match bounds {
+            Bounds::Any => node,
+            Bounds::Word => Node::Concat(vec![
+                Node::Look(Look::NotAfterWord),
+                node,
+                Node::Look(Look::NotBeforeWord),
+            ]),
+            Bounds::Line => Node::Concat(vec![
+                Node::Look(Look::LineStart),
+                node,
+                Node::Look(Look::LineEnd),
+            ]),
+        };
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+        };
+        // Slots 0 and 1 always hold the bounds of the overall match
+        compiler.push(Inst::Save(0));
+        compiler.compile(&node)?;
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+
+        Ok(Regex {
+            program: compiler.program,
+            slots: (parser.groups + 1) * 2,
+            ignore_case,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    // Finds the leftmost match starting at or after the byte offset 'start'. Anchors and word
+    // boundaries still look at the text before 'start'
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let slots = self.exec(text, start)?;
+        Some((slots[0]?, slots[1]?))
+    }
+
+    // Iterates over the successive non-overlapping matches in 'text'
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> FindIter<'r, 't> {
+        FindIter {
+            captures: self.captures_iter(text),
+        }
+    }
+
+    // Number of capture groups, including group 0 for the whole match
+    pub fn captures_len(&self) -> usize {
+        self.slots / 2
+    }
+
+    // Like 'find_at' but also returns the bounds of every capture group, indexed by the number
+    // of its opening parenthesis. Groups that didn't take part in the match are None
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let slots = self.exec(text, start)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|bounds| Some((bounds[0]?, bounds[1]?)))
+                .collect(),
+        )
+    }
+
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> CapturesIter<'r, 't> {
+        CapturesIter {
+            regex: self,
+            text,
+            pos: 0,
+        }
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut current: Vec<Thread> = Vec::new();
+        let mut next: Vec<Thread> = Vec::new();
+        // 'visited[pc]' holds the last step in which 'pc' was added to a thread list, so each
+        // instruction is only ever added once per position
+        let mut visited = vec![usize::MAX; self.program.len()];
+        let mut matched = None;
+        let mut pos = start;
+        let mut step = 0;
+
+        loop {
+            // Seeding a new thread at every position makes the search unanchored. It is added
+            // last so it has the lowest priority, which gives leftmost-first semantics
+            if matched.is_none() {
+                let slots = vec![None; self.slots];
+                self.add_thread(&mut current, &mut visited, step, 0, slots, text, pos);
+            }
+            if current.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let ch = text[pos..].chars().next();
+            for thread in current.drain(..) {
+                let consumed = match &self.program[thread.pc] {
+                    Inst::Match => {
+                        // Every remaining thread has a lower priority than this one
+                        matched = Some(thread.slots);
+                        break;
+                    }
+                    Inst::Char(expected) => ch.filter(|&c| self.char_eq(*expected, c)),
+                    Inst::Any => ch.filter(|&c| c != '\n'),
+                    Inst::Class(class) => ch.filter(|&c| class.matches(c, self.ignore_case)),
+                    _ => None,
+                };
+
+                if let Some(c) = consumed {
+                    let next_pos = pos + c.len_utf8();
+                    self.add_thread(
+                        &mut next,
+                        &mut visited,
+                        step + 1,
+                        thread.pc + 1,
+                        thread.slots,
+                        text,
+                        next_pos,
+                    );
+                }
+            }
+
+            match ch {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            step += 1;
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        matched
+    }
+
+    // Follows every non-consuming instruction reachable from 'pc', adding the threads that end
+    // up on a consuming instruction (or 'Match') to 'list' in priority order
+    #[allow(clippy::too_many_arguments)]
+    fn add_thread(
+        &self,
+        list: &mut Vec<Thread>,
+        visited: &mut [usize],
+        step: usize,
+        pc: usize,
+        slots: Vec<Option<usize>>,
+        text: &str,
+        pos: usize,
+    ) {
+        let mut stack = vec![(pc, slots)];
+
+        while let Some((pc, mut slots)) = stack.pop() {
+            if visited[pc] == step {
+                continue;
+            }
+            visited[pc] = step;
+
+            match &self.program[pc] {
+                Inst::Jmp(target) => stack.push((*target, slots)),
+                Inst::Split(first, second) => {
+                    // The stack is LIFO, so 'first' is explored completely before 'second'
+                    stack.push((*second, slots.clone()));
+                    stack.push((*first, slots));
+                }
+                Inst::Save(slot) => {
+                    slots[*slot] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Look(look) => {
+                    if look.holds(text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => list.push(Thread { pc, slots }),
+            }
+        }
+    }
+
+    fn char_eq(&self, expected: char, c: char) -> bool {
+        expected == c || (self.ignore_case && fold(expected) == fold(c))
+    }
+}
+
+// Bounds of the whole match followed by the bounds of every capture group
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+pub struct CapturesIter<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    pos: usize,
+}
+
+impl Iterator for CapturesIter<'_, '_> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Captures> {
+        if self.pos > self.text.len() {
+            return None;
+        }
+
+        let captures = self.regex.captures_at(self.text, self.pos)?;
+        let (start, end) = captures[0]?;
+        // Step over empty matches so the same position isn't matched again forever
+        self.pos = match start == end {
+            true => end + self.text[end..].chars().next().map_or(1, char::len_utf8),
+            false => end,
+        };
+
+        Some(captures)
+    }
+}
+
+pub struct FindIter<'r, 't> {
+    captures: CapturesIter<'r, 't>,
+}
+
+impl Iterator for FindIter<'_, '_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        self.captures.next()?[0]
+    }
+}
+
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Save(usize),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Look {
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    NotWordBoundary,
+    // Halves of a word boundary, only used for 'Bounds::Word'. Unlike '\b' they also hold for
+    // matches starting or ending with a character that isn't a word character
+    NotAfterWord,
+    NotBeforeWord,
+}
+
+impl Look {
+    fn holds(self, text: &str, pos: usize) -> bool {
+        let before = text[..pos].chars().next_back();
+        let after = text[pos..].chars().next();
+
+        match self {
+            Look::LineStart => before.is_none_or(|c| c == '\n'),
+            Look::LineEnd => after.is_none_or(|c| c == '\n'),
+            Look::WordBoundary => is_word(before) != is_word(after),
+            Look::NotWordBoundary => is_word(before) == is_word(after),
+            Look::NotAfterWord => !is_word(before),
+            Look::NotBeforeWord => !is_word(after),
+        }
+    }
+}
+
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_numeric(),
+            Perl::Word => c.is_alphanumeric() || c == '_',
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(start, end) => start <= c && c <= end,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn perl(perl: Perl, negated: bool) -> Class {
+        Class {
+            items: vec![ClassItem::Perl(perl, negated)],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let mut hit = self.items.iter().any(|item| item.matches(c));
+        if !hit && ignore_case {
+            hit = c
+                .to_lowercase()
+                .chain(c.to_uppercase())
+                .chain(std::iter::once(fold(c)))
+                .any(|v| self.items.iter().any(|item| item.matches(v)));
+        }
+        hit != self.negated
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Look(Look),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, Error> {
+        let node = self.parse_alternate()?;
+        if self.pos < self.chars.len() {
+            // 'parse_alternate' only stops early on a ')' without an opening '('
+            return Err(self.error("unmatched ')'"));
+        }
+        Ok(node)
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            message: message.to_string(),
+            position: self.pos,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternate(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        match branches.len() {
+            1 => Ok(branches.pop().unwrap()),
+            _ => Ok(Node::Alternate(branches)),
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.parse_repeat()?);
+        }
+
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, Error> {
+        let mut node = self.parse_atom()?;
+
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    (0, None)
+                }
+                Some('+') => {
+                    self.pos += 1;
+                    (1, None)
+                }
+                Some('?') => {
+                    self.pos += 1;
+                    (0, Some(1))
+                }
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    None => break,
+                },
+                _ => break,
+            };
+            let greedy = !self.eat('?');
+
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+
+        Ok(node)
+    }
+
+    // Parses '{n}', '{n,}' or '{n,m}'. Anything else starting with '{' is left alone so it can be
+    // treated as a literal, which keeps queries like 'fn main() {' working
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, Error> {
+        let start = self.pos;
+        self.pos += 1;
+
+        let min = self.parse_number();
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            min
+        };
+
+        if min.is_none() || !self.eat('}') {
+            self.pos = start;
+            return Ok(None);
+        }
+
+        let min = min.unwrap();
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            self.pos = start;
+            return Err(self.error("repetition count is too large"));
+        }
+        if let Some(max) = max {
+            if max < min {
+                self.pos = start;
+                return Err(self.error("invalid repetition range"));
+            }
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(Node::Empty),
+        };
+
+        match c {
+            '(' => self.parse_group(),
+            '[' => {
+                self.pos += 1;
+                Ok(Node::Class(self.parse_class()?))
+            }
+            '*' | '+' | '?' => Err(self.error("repetition operator missing expression")),
+            '.' => {
+                self.pos += 1;
+                Ok(Node::Any)
+            }
+            '^' => {
+                self.pos += 1;
+                Ok(Node::Look(Look::LineStart))
+            }
+            '$' => {
+                self.pos += 1;
+                Ok(Node::Look(Look::LineEnd))
+            }
+            '\\' => {
+                self.pos += 1;
+                self.parse_escape()
+            }
+            _ => {
+                self.pos += 1;
+                Ok(Node::Char(c))
+            }
+        }
+    }
+
+    fn parse_group(&mut self) -> Result<Node, Error> {
+        let open = self.pos;
+        self.pos += 1;
+
+        let index = if self.eat('?') {
+            if !self.eat(':') {
+                return Err(self.error("unsupported group syntax"));
+            }
+            None
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+
+        let node = self.parse_alternate()?;
+        if !self.eat(')') {
+            return Err(Error {
+                message: "unclosed group".to_string(),
+                position: open,
+            });
+        }
+
+        Ok(Node::Group(Box::new(node), index))
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, Error> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("trailing backslash")),
+        };
+
+        let node = match c {
+            'b' => Node::Look(Look::WordBoundary),
+            'B' => Node::Look(Look::NotWordBoundary),
+            _ => match self.parse_class_escape(c)? {
+                Some(class) => Node::Class(class),
+                None => Node::Char(self.parse_char_escape(c)?),
+            },
+        };
+        self.pos += 1;
+
+        Ok(node)
+    }
+
+    fn parse_class_escape(&self, c: char) -> Result<Option<Class>, Error> {
+        let class = match c {
+            'd' => Class::perl(Perl::Digit, false),
+            'D' => Class::perl(Perl::Digit, true),
+            'w' => Class::perl(Perl::Word, false),
+            'W' => Class::perl(Perl::Word, true),
+            's' => Class::perl(Perl::Space, false),
+            'S' => Class::perl(Perl::Space, true),
+            _ => return Ok(None),
+        };
+        Ok(Some(class))
+    }
+
+    fn parse_char_escape(&self, c: char) -> Result<char, Error> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            _ if c.is_alphanumeric() => Err(self.error("unrecognized escape sequence")),
+            _ => Ok(c),
+        }
+    }
+
+    // Called with 'pos' just after the opening '['
+    fn parse_class(&mut self) -> Result<Class, Error> {
+        let open = self.pos - 1;
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    return Err(Error {
+                        message: "unclosed character class".to_string(),
+                        position: open,
+                    })
+                }
+            };
+
+            // A ']' right after the opening bracket is a literal
+            if c == ']' && !first {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+
+            let start = match self.parse_class_char()? {
+                ClassChar::Char(c) => c,
+                ClassChar::Perl(class) => {
+                    items.extend(class.items);
+                    continue;
+                }
+            };
+
+            let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+            if !is_range {
+                items.push(ClassItem::Range(start, start));
+                continue;
+            }
+
+            self.pos += 1;
+            let end = match self.parse_class_char()? {
+                ClassChar::Char(c) => c,
+                ClassChar::Perl(_) => return Err(self.error("invalid character class range")),
+            };
+            if end < start {
+                return Err(self.error("invalid character class range"));
+            }
+            items.push(ClassItem::Range(start, end));
+        }
+
+        Ok(Class { items, negated })
+    }
+
+    fn parse_class_char(&mut self) -> Result<ClassChar, Error> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("unclosed character class")),
+        };
+        self.pos += 1;
+
+        if c != '\\' {
+            return Ok(ClassChar::Char(c));
+        }
+
+        let escaped = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("trailing backslash")),
+        };
+        let result = match self.parse_class_escape(escaped)? {
+            Some(class) => ClassChar::Perl(class),
+            None => ClassChar::Char(self.parse_char_escape(escaped)?),
+        };
+        self.pos += 1;
+
+        Ok(result)
+    }
+}
+
+enum ClassChar {
+    Char(char),
+    Perl(Class),
+}
+
+struct Compiler {
+    program: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), Error> {
+        if self.program.len() > MAX_PROGRAM_SIZE {
+            return Err(Error {
+                message: "pattern is too large".to_string(),
+                position: 0,
+            });
+        }
+
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.push(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.push(Inst::Class(class.clone()));
+            }
+            Node::Look(look) => {
+                self.push(Inst::Look(*look));
+            }
+            Node::Group(node, index) => match index {
+                Some(index) => {
+                    self.push(Inst::Save(index * 2));
+                    self.compile(node)?;
+                    self.push(Inst::Save(index * 2 + 1));
+                }
+                None => self.compile(node)?,
+            },
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            Node::Alternate(branches) => self.compile_alternate(branches)?,
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.compile_repeat(node, *min, *max, *greedy)?,
+        }
+
+        Ok(())
+    }
+
+    fn compile_alternate(&mut self, branches: &[Node]) -> Result<(), Error> {
+        let mut jumps = Vec::new();
+
+        for (i, branch) in branches.iter().enumerate() {
+            if i == branches.len() - 1 {
+                self.compile(branch)?;
+                break;
+            }
+
+            let split = self.push(Inst::Split(0, 0));
+            self.compile(branch)?;
+            jumps.push(self.push(Inst::Jmp(0)));
+            let next = self.program.len();
+            self.program[split] = Inst::Split(split + 1, next);
+        }
+
+        let end = self.program.len();
+        for jump in jumps {
+            self.program[jump] = Inst::Jmp(end);
+        }
+
+        Ok(())
+    }
+
+    fn compile_repeat(
+        &mut self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    ) -> Result<(), Error> {
+        // A body that compiles to nothing, like '(?:)', only matches the empty string however
+        // many times it's repeated
+        let start = self.program.len();
+        self.compile(node)?;
+        let empty = self.program.len() == start;
+        self.program.truncate(start);
+        if empty {
+            return Ok(());
+        }
+
+        for _ in 0..min {
+            self.compile(node)?;
+        }
+
+        match max {
+            None => {
+                let split = self.push(Inst::Split(0, 0));
+                self.compile(node)?;
+                self.push(Inst::Jmp(split));
+                let end = self.program.len();
+                self.program[split] = self.split(split + 1, end, greedy);
+            }
+            Some(max) => {
+                for _ in min..max {
+                    let split = self.push(Inst::Split(0, 0));
+                    self.compile(node)?;
+                    let end = self.program.len();
+                    self.program[split] = self.split(split + 1, end, greedy);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn split(&self, body: usize, end: usize, greedy: bool) -> Inst {
+        match greedy {
+            true => Inst::Split(body, end),
+            false => Inst::Split(end, body),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::regex::Captures;
+use crate::{is_binary, Error, Patterns};
+
+// Replacement text for '--replace'. '$N' and '${N}' insert capture group N of the match, with
+// '$0' being the whole match, and '$$' inserts a '$'. A '$' that doesn't start a reference is
+// kept as it is. Groups only exist for regular expression queries, so for plain queries '$0' is
+// the only reference that isn't replaced with nothing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Group(usize),
+}
+
+impl Replacement {
+    pub fn new(text: &str) -> Replacement {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+
+        while let Some(i) = rest.find('$') {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (digits, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.split_once('}') {
+                    Some((digits, after)) => (digits, after),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+
+            match digits.parse() {
+                Ok(group) if digits.bytes().all(|b| b.is_ascii_digit()) => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Group(group));
+                    rest = after;
+                }
+                _ => literal.push('$'),
+            }
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Replacement { pieces }
+    }
+
+    fn expand(&self, text: &str, captures: &Captures, out: &mut String) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => out.push_str(literal),
+                Piece::Group(group) => {
+                    if let Some(&Some((start, end))) = captures.get(*group) {
+                        out.push_str(&text[start..end]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Replaces every match in 'line', or returns None when nothing matches
+pub(crate) fn replace_line(
+    matcher: &Patterns,
+    replacement: &Replacement,
+    line: &str,
+) -> Option<String> {
+    let captures: Vec<Captures> = match matcher {
+        Patterns::Regex(regex) => regex.captures_iter(line).collect(),
+        // The captures come from whichever regex produced the match
+        Patterns::Regexes(regexes) => matcher
+            .find_all(line)
+            .into_iter()
+            .map(|(start, end)| {
+                regexes
+                    .iter()
+                    .filter_map(|regex| regex.captures_at(line, start))
+                    .find(|captures| captures[0] == Some((start, end)))
+                    .unwrap_or_else(|| vec![Some((start, end))])
+            })
+            .collect(),
+        _ => matcher
+            .find_all(line)
+            .into_iter()
+            .map(|bounds| vec![Some(bounds)])
+            .collect(),
+    };
+    if captures.is_empty() {
+        return None;
+    }
+
+    let mut replaced = String::with_capacity(line.len());
+    let mut copied = 0;
+    for captures in &captures {
+        let Some((start, end)) = captures[0] else {
+            continue;
+        };
+        replaced.push_str(&line[copied..start]);
+        replacement.expand(line, captures, &mut replaced);
+        copied = end;
+    }
+    replaced.push_str(&line[copied..]);
+
+    Some(replaced)
+}
+
+// Rewrites the file at 'path' with every match replaced. The new contents are written to a
+// temporary file next to it which is then renamed over the original, so the file is never left
+// half written. A symlink is followed and the file it points to rewritten, leaving the link in
+// place. Returns whether anything was replaced. Binary files are left alone unless 'binary' is
+// set
+pub(crate) fn in_place(
+    matcher: &Patterns,
+    replacement: &Replacement,
+    path: &str,
+    binary: bool,
+) -> Result<bool, Error> {
+    let path = &fs::canonicalize(path)?;
+    let contents = fs::read(path)?;
+    if !binary && is_binary(&mut contents.as_slice())? {
+        return Ok(false);
+    }
+    let contents = String::from_utf8(contents)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut replaced = String::with_capacity(contents.len());
+    let mut count = 0;
+    for line in contents.split_inclusive('\n') {
+        let text = crate::trim_line_ending(line);
+        match replace_line(matcher, replacement, text) {
+            Some(new) => {
+                replaced.push_str(&new);
+                replaced.push_str(&line[text.len()..]);
+                count += 1;
+            }
+            None => replaced.push_str(line),
+        }
+    }
+
+    if count == 0 {
+        return Ok(false);
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.minigrep-{}", std::process::id()));
+    let result = write_file(
+        &temp,
+        replaced.as_bytes(),
+        &fs::metadata(path)?.permissions(),
+    )
+    .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    Ok(true)
+}
+
+fn write_file(path: &Path, contents: &[u8], permissions: &fs::Permissions) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.set_permissions(permissions.clone())?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use crate::Regex;
+
+    fn replace(pattern: &str, replacement: &str, line: &str) -> Option<String> {
+        let matcher = Patterns::Regex(Regex::new(pattern).unwrap());
+        replace_line(&matcher, &Replacement::new(replacement), line)
+    }
+
+    #[test]
+    fn capture_group_references() {
+        assert_eq!(
+            Some("Rust is fun, Go is fine".to_string()),
+            replace(r"(\w+) is (\w+)", "$1 is ${2}", "Rust is fun, Go is fine")
+        );
+        assert_eq!(
+            Some("fun-Rust, fine-Go".to_string()),
+            replace(r"(\w+) is (\w+)", "$2-$1", "Rust is fun, Go is fine")
+        );
+        assert_eq!(
+            Some("[$5 and $x] [$5 and $x]".to_string()),
+            replace("a+", "[$$5 and $x]", "aa aaa")
+        );
+        assert_eq!(
+            Some("<> a<>".to_string()),
+            replace("(x)?b+", "<$1>", "bb ab")
+        );
+        assert_eq!(None, replace("z", "$0", "aa aaa"));
+    }
+
+    #[test]
+    fn plain_queries() {
+        let matcher = Patterns::CaseInsensitive(crate::search::CaseInsensitive::new("us"));
+        let replacement = Replacement::new("[$0$1]");
+
+        assert_eq!(
+            Some("They'd banish [us], you know. [US]!".to_string()),
+            replace_line(&matcher, &replacement, "They'd banish us, you know. US!")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rewrites_files_in_place() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("replace");
+        let path = dir.0.join("fish.txt");
+        fs::write(&path, "one fish\r\ntwo fish\nred fish\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let matcher = Patterns::CaseSensitive(crate::search::Literal::new("fish"));
+        let replacement = Replacement::new("cat");
+        let file = path.to_str().unwrap();
+
+        let replaced = in_place(&matcher, &replacement, file, false).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+
+        assert!(replaced);
+        assert_eq!("one cat\r\ntwo cat\nred cat\n", contents);
+        assert_eq!(0o640, mode & 0o777);
+        assert!(!in_place(&matcher, &replacement, file, false).unwrap());
+
+        // A link stays a link and the file it points to is rewritten
+        let link = dir.0.join("link.txt");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        let replacement = Replacement::new("dog");
+        let matcher = Patterns::CaseSensitive(crate::search::Literal::new("cat"));
+        assert!(in_place(&matcher, &replacement, link.to_str().unwrap(), false).unwrap());
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            "one dog\r\ntwo dog\nred dog\n",
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+}
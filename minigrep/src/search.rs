@@ -0,0 +1,713 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::case;
+use crate::decompress::decompress;
+use crate::encoding::{self, Decoder, Encoding};
+use crate::fuzzy::Fuzzy;
+use crate::literal::{self, Finder};
+use crate::regex::Bounds;
+use crate::{
+    is_binary, multiline, search_lines, Error, Event, Line, LineKind, MmapChoice, Patterns, Regex,
+    SearchOptions,
+};
+
+// The pieces 'run' is built from, for programs embedding minigrep. A 'Matcher' finds the matches
+// in a line, a 'Searcher' reads an input and decides which lines are selected or context, and a
+// 'Sink' receives those lines. Printing results the way the command line does is just the sink
+// 'run' uses
+//
+//   let matcher = Literal::new("frog");
+//   let mut lines = Vec::new();
+//   Searcher::default().search_path(&matcher, "poem.txt", &mut |line: &Line<'_>| {
+//       lines.push(line.number);
+//       Ok(())
+//   })?;
+
+pub trait Matcher {
+    // Byte offsets of every non-overlapping match in 'line' from left to right, empty when it
+    // doesn't match
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)>;
+
+    // Like 'find_all', but also returns the index of the pattern that produced the first match,
+    // for matchers searching for several patterns at once
+    fn find(&self, line: &str) -> (Vec<(usize, usize)>, usize) {
+        (self.find_all(line), 0)
+    }
+
+    // How many bytes at the start of 'buffer', a run of lines straight from the input, can't hold
+    // the start of a match. The searcher skips the whole lines in them without splitting them,
+    // so a matcher that can search the buffer directly returns the offset of its first match, or
+    // the length of the buffer when there is none. The default of 0 searches every line
+    fn skip(&self, _buffer: &[u8]) -> usize {
+        0
+    }
+}
+
+// A plain query, matched exactly
+#[derive(Debug, Clone)]
+pub struct Literal {
+    query: String,
+    // Boxed since its skip table is a couple of kilobytes
+    finder: Box<Finder>,
+    bounds: Bounds,
+}
+
+impl Literal {
+    pub fn new(query: &str) -> Literal {
+        Literal::bounded(query, Bounds::Any)
+    }
+
+    // Like 'new', but only matching where 'bounds' hold, the way '-w' and '-x' do. The query is
+    // still searched for directly and only what surrounds each occurrence is checked
+    pub fn bounded(query: &str, bounds: Bounds) -> Literal {
+        Literal {
+            query: query.to_string(),
+            finder: Box::new(Finder::new(query.as_bytes())),
+            bounds,
+        }
+    }
+}
+
+impl Matcher for Literal {
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        if self.query.is_empty() {
+            return vec![(0, 0)];
+        }
+
+        find_bounded(line, self.bounds, |start| {
+            let found = self.finder.find_at(line.as_bytes(), start)?;
+            Some((found, found + self.query.len()))
+        })
+    }
+
+    fn skip(&self, buffer: &[u8]) -> usize {
+        // Lines are searched with invalid UTF-8 replaced by U+FFFD, which the buffer doesn't have
+        if self.query.contains(char::REPLACEMENT_CHARACTER) {
+            return 0;
+        }
+        self.finder.find_at(buffer, 0).unwrap_or(buffer.len())
+    }
+}
+
+// A plain query, matched ignoring case the way '-i' does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseInsensitive {
+    folded: String,
+    bounds: Bounds,
+}
+
+impl CaseInsensitive {
+    pub fn new(query: &str) -> CaseInsensitive {
+        CaseInsensitive::bounded(query, Bounds::Any)
+    }
+
+    // Like 'new', but only matching where 'bounds' hold, see 'Literal::bounded'
+    pub fn bounded(query: &str, bounds: Bounds) -> CaseInsensitive {
+        CaseInsensitive {
+            folded: case::fold_str(query),
+            bounds,
+        }
+    }
+}
+
+impl Matcher for CaseInsensitive {
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        match self.bounds {
+            Bounds::Any => case::find_all(&self.folded, line),
+            bounds => find_bounded(line, bounds, |start| {
+                case::find_at(&self.folded, line, start)
+            }),
+        }
+    }
+
+    // The buffer has to be checked to be UTF-8 before it can be searched, which costs as much as
+    // searching it, so it's searched in growing chunks of whole lines and only as far as the
+    // first match. A match can't span invalid bytes, so there is none before them either
+    fn skip(&self, buffer: &[u8]) -> usize {
+        if self.folded.contains(char::REPLACEMENT_CHARACTER) {
+            return 0;
+        }
+
+        let mut start = 0;
+        let mut size = 128;
+        while start < buffer.len() {
+            let end = buffer.len().min(start + size);
+            size *= 2;
+            let Some(newline) = literal::memrchr(b'\n', &buffer[start..end]) else {
+                match end == buffer.len() {
+                    true => break,
+                    false => continue,
+                }
+            };
+
+            let chunk = &buffer[start..=start + newline];
+            let (valid, invalid) = match std::str::from_utf8(chunk) {
+                Ok(valid) => (valid, false),
+                Err(err) => (
+                    std::str::from_utf8(&chunk[..err.valid_up_to()]).unwrap(),
+                    true,
+                ),
+            };
+            if let Some((found, _)) = case::find_at(&self.folded, valid, 0) {
+                return start + found;
+            }
+            if invalid {
+                return start + valid.len();
+            }
+            start += chunk.len();
+        }
+        start
+    }
+}
+
+// Every non-overlapping occurrence 'find_at' finds at or after a byte offset for which 'bounds'
+// hold. An occurrence they don't hold for may overlap one they do, so the search goes on right
+// after its first character rather than after its end. Occurrences must not be empty
+fn find_bounded(
+    line: &str,
+    bounds: Bounds,
+    mut find_at: impl FnMut(usize) -> Option<(usize, usize)>,
+) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while let Some((found, end)) = find_at(start) {
+        if bounds.hold(line, found, end) {
+            matches.push((found, end));
+            start = end;
+        } else {
+            start = found + line[found..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    matches
+}
+
+impl Matcher for Regex {
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        self.find_iter(line).collect()
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        Fuzzy::find_all(self, line)
+            .into_iter()
+            .map(|m| (m.start, m.end))
+            .collect()
+    }
+}
+
+impl Matcher for Patterns {
+    fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        Patterns::find_all(self, line)
+    }
+
+    fn find(&self, line: &str) -> (Vec<(usize, usize)>, usize) {
+        Patterns::find(self, line)
+    }
+
+    fn skip(&self, buffer: &[u8]) -> usize {
+        match self {
+            Patterns::CaseSensitive(literal) => literal.skip(buffer),
+            Patterns::CaseInsensitive(query) => query.skip(buffer),
+            _ => 0,
+        }
+    }
+}
+
+// Receives the lines of an input in order as the searcher finds them. Lines borrow from the
+// searcher's buffer, so a sink keeping them has to copy what it needs. An error stops the search
+// and is returned by the searcher
+pub trait Sink {
+    // A selected line: one with a match, or with 'invert_match' one without
+    fn matched(&mut self, line: &Line<'_>) -> Result<(), Error>;
+
+    // A line of context before or after a selected one
+    fn context(&mut self, _line: &Line<'_>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Called before every group of contiguous lines, including the first one
+    fn group_start(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Called once the whole input was searched
+    fn finish(&mut self, _finish: &Finish) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// A closure is a sink receiving only the selected lines
+impl<F: FnMut(&Line<'_>) -> Result<(), Error>> Sink for F {
+    fn matched(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        self(line)
+    }
+}
+
+// What a searcher tells its sink about a finished input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Finish {
+    pub matched_lines: u64,
+    // Matches in the selected lines, none with 'invert_match'
+    pub matches: u64,
+    // The input looked binary, so its lines weren't passed to the sink, see 'Searcher::binary'
+    pub binary: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Searcher {
+    pub options: SearchOptions,
+    // Search binary inputs as text. Otherwise searching one stops at the first selected line and
+    // only 'finish' is called, since its lines are rarely worth looking at
+    pub binary: bool,
+    // Search every input as a whole so matches can span lines, see the 'multiline' module
+    pub multiline: bool,
+    // Search inside compressed inputs, see the 'decompress' module
+    pub decompress: bool,
+    // Read inputs in this encoding instead of the one their byte order mark names or UTF-8, see
+    // the 'encoding' module
+    pub encoding: Option<Encoding>,
+    // Whether 'search_path' maps files instead of reading them
+    pub mmap: MmapChoice,
+}
+
+impl Searcher {
+    pub fn new(options: SearchOptions) -> Searcher {
+        Searcher {
+            options,
+            ..Searcher::default()
+        }
+    }
+
+    pub fn search_reader(
+        &self,
+        matcher: &impl Matcher,
+        reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> Result<(), Error> {
+        match self.decompress {
+            true => self.search_decompressed(matcher, decompress(reader)?, sink),
+            false => self.search_decompressed(matcher, reader, sink),
+        }
+    }
+
+    fn search_decompressed(
+        &self,
+        matcher: &impl Matcher,
+        mut reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> Result<(), Error> {
+        match encoding::detect(reader.fill_buf()?, self.encoding) {
+            Some((encoding, bom)) => {
+                reader.consume(bom);
+                match encoding {
+                    Encoding::Utf8 => self.search_text(matcher, reader, sink),
+                    encoding => self.search_text(matcher, Decoder::new(reader, encoding), sink),
+                }
+            }
+            None => self.search_text(matcher, reader, sink),
+        }
+    }
+
+    // Errors are reported with 'path', as 'run' does
+    pub fn search_path(
+        &self,
+        matcher: &impl Matcher,
+        path: impl AsRef<Path>,
+        sink: &mut impl Sink,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        let result = File::open(path).map_err(Error::from).and_then(|file| {
+            match map(&file, self.mmap).filter(|_| !self.decompress) {
+                Some(mmap) => self.search_reader(matcher, &mmap[..], sink),
+                None => self.search_reader(matcher, BufReader::new(file), sink),
+            }
+        });
+
+        result.map_err(|err| err.with_path(&path.display().to_string()))
+    }
+
+    fn search_text(
+        &self,
+        matcher: &impl Matcher,
+        mut reader: impl BufRead,
+        sink: &mut impl Sink,
+    ) -> Result<(), Error> {
+        if !self.multiline {
+            return self.search_lines(
+                reader,
+                |_, line| matcher.find(line),
+                |buffer| matcher.skip(buffer),
+                sink,
+            );
+        }
+
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        let contents = String::from_utf8_lossy(&contents);
+        let lines = multiline::line_matches(matcher, &contents);
+        self.search_lines(
+            contents.as_bytes(),
+            |number, _| {
+                lines.get(&number).map_or_else(Default::default, |line| {
+                    (line.matches.clone(), line.pattern)
+                })
+            },
+            |_| 0,
+            &mut Blocks {
+                lines: &lines,
+                sink,
+            },
+        )
+    }
+
+    fn search_lines(
+        &self,
+        mut reader: impl BufRead,
+        find_matches: impl Fn(u32, &str) -> (Vec<(usize, usize)>, usize),
+        skip: impl Fn(&[u8]) -> usize,
+        sink: &mut impl Sink,
+    ) -> Result<(), Error> {
+        let binary = !self.binary && is_binary(&mut reader)?;
+        let mut options = self.options;
+        if binary {
+            options.before_context = 0;
+            options.after_context = 0;
+            options.max_count = Some(1);
+        }
+        let mut finish = Finish {
+            binary,
+            ..Finish::default()
+        };
+
+        search_lines(reader, find_matches, skip, &options, |event| match event {
+            _ if binary => {
+                finish.matched_lines += matches!(event, Event::Line(_)) as u64;
+                Ok(())
+            }
+            Event::GroupStart => sink.group_start(),
+            Event::Line(line) if line.kind == LineKind::Match => {
+                finish.matched_lines += 1;
+                finish.matches += line.matches.len() as u64;
+                sink.matched(&line)
+            }
+            Event::Line(line) => sink.context(&line),
+        })?;
+
+        sink.finish(&finish)
+    }
+}
+
+// Passes the lines of a multi-line search on to 'sink' with the blocks their matches are part of
+struct Blocks<'s, S> {
+    lines: &'s HashMap<u32, multiline::LineMatches>,
+    sink: &'s mut S,
+}
+
+impl<S: Sink> Sink for Blocks<'_, S> {
+    fn matched(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        match self.lines.get(&line.number) {
+            Some(found) => self.sink.matched(&Line {
+                blocks: found.blocks.clone(),
+                ..line.clone()
+            }),
+            None => self.sink.matched(line),
+        }
+    }
+
+    fn context(&mut self, line: &Line<'_>) -> Result<(), Error> {
+        self.sink.context(line)
+    }
+
+    fn group_start(&mut self) -> Result<(), Error> {
+        self.sink.group_start()
+    }
+
+    fn finish(&mut self, finish: &Finish) -> Result<(), Error> {
+        self.sink.finish(finish)
+    }
+}
+
+// Maps 'file' when 'choice' asks for it. Pipes, devices and files that fail to map are read
+// instead
+fn map(file: &File, choice: MmapChoice) -> Option<Mmap> {
+    let metadata = file.metadata().ok()?;
+    if !metadata.is_file() || !choice.maps(metadata.len()) {
+        return None;
+    }
+
+    // SAFETY: the mapping is only read, but another process truncating or rewriting the file
+    // while it is searched can change the bytes under it or make reading them fail. Every other
+    // grep that maps files accepts the same, and reading the file would race the same way
+    unsafe { Mmap::map(file) }.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POEM: &str = include_str!("../poem.txt");
+
+    // Records every callback as a line of text
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Sink for Recorder {
+        fn matched(&mut self, line: &Line<'_>) -> Result<(), Error> {
+            self.0.push(format!("{}:{:?}", line.number, line.matches));
+            Ok(())
+        }
+
+        fn context(&mut self, line: &Line<'_>) -> Result<(), Error> {
+            self.0.push(format!("{}-", line.number));
+            Ok(())
+        }
+
+        fn group_start(&mut self) -> Result<(), Error> {
+            self.0.push("--".to_string());
+            Ok(())
+        }
+
+        fn finish(&mut self, finish: &Finish) -> Result<(), Error> {
+            self.0.push(format!("{finish:?}"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn matchers() {
+        assert_eq!(
+            vec![(19, 23)],
+            Literal::new("frog").find_all("How public, like a frog")
+        );
+        assert!(Literal::new("Frog")
+            .find_all("How public, like a frog")
+            .is_empty());
+        assert_eq!(
+            vec![(19, 23)],
+            CaseInsensitive::new("FROG").find_all("How public, like a frog")
+        );
+        assert_eq!(
+            vec![(4, 10), (17, 25)],
+            Regex::new(r"\w+y\b")
+                .unwrap()
+                .find_all("How dreary to be somebody!")[..2]
+        );
+        assert_eq!(
+            vec![(7, 10)],
+            Matcher::find_all(&Fuzzy::new("frgo", 1, false), "like a frog")
+        );
+    }
+
+    #[test]
+    fn bounded_literals() {
+        let word = |query: &str, line: &str| Literal::bounded(query, Bounds::Word).find_all(line);
+        assert_eq!(vec![(6, 8)], word("aa", "aaa a aa"));
+        // An occurrence that isn't a word can overlap one that is
+        assert_eq!(vec![(3, 6)], word("a-a", "ba-a-a"));
+        assert_eq!(vec![(5, 8)], word("éa", "éé éa"));
+        assert_eq!(
+            vec![(0, 2), (9, 11)],
+            CaseInsensitive::bounded("US", Bounds::Word).find_all("us, bus, us")
+        );
+
+        let line = Literal::bounded("frog", Bounds::Line);
+        assert_eq!(vec![(0, 4)], line.find_all("frog"));
+        assert!(line.find_all("a frog").is_empty());
+        // Multiline searches hand over several lines at once
+        assert_eq!(vec![(7, 11)], line.find_all("a frog\nfrog\n"));
+
+        // The same matches as the regular expression engine finds
+        for query in ["to", "-", "- don't", "us"] {
+            for bounds in [Bounds::Word, Bounds::Line] {
+                let regex = Regex::build(&crate::regex::escape(query), true, bounds).unwrap();
+                let literal = CaseInsensitive::bounded(query, bounds);
+                for line in POEM.lines() {
+                    assert_eq!(
+                        regex.find_all(line),
+                        literal.find_all(line),
+                        "{query} in {line}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_lines_without_matches() {
+        let buffer = b"I'm nobody!\nlike a frog\n";
+        assert_eq!(19, Literal::new("frog").skip(buffer));
+        assert_eq!(buffer.len(), Literal::new("toad").skip(buffer));
+        assert_eq!(19, CaseInsensitive::new("FROG").skip(buffer));
+        // Nothing after invalid UTF-8 is searched
+        assert_eq!(2, CaseInsensitive::new("FROG").skip(b"a\n\xff\nfrog\n"));
+
+        // Lines are numbered the same whether they were skipped or searched, also across the
+        // ends of the reader's buffer
+        fn search(matcher: &impl Matcher) -> Vec<String> {
+            let mut lines = Vec::new();
+            let reader = BufReader::with_capacity(16, POEM.as_bytes());
+            Searcher::default()
+                .search_reader(matcher, reader, &mut |line: &Line<'_>| {
+                    lines.push(format!(
+                        "{}@{}:{:?}",
+                        line.number, line.offset, line.matches
+                    ));
+                    Ok(())
+                })
+                .unwrap();
+            lines
+        }
+        let regex = Regex::build("to", true, crate::regex::Bounds::Any).unwrap();
+        assert_eq!(search(&regex), search(&CaseInsensitive::new("TO")));
+        assert_eq!(
+            search(&Regex::new("to").unwrap()),
+            search(&Literal::new("to"))
+        );
+    }
+
+    #[test]
+    fn sink_callbacks() {
+        let searcher = Searcher::new(SearchOptions {
+            after_context: 1,
+            ..SearchOptions::default()
+        });
+        let mut recorder = Recorder::default();
+        searcher
+            .search_reader(&Literal::new("nobody"), POEM.as_bytes(), &mut recorder)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "--",
+                "1:[(4, 10)]",
+                "2:[(8, 14)]",
+                "3-",
+                "Finish { matched_lines: 2, matches: 2, binary: false }"
+            ],
+            recorder.0
+        );
+    }
+
+    #[test]
+    fn closures_are_sinks() {
+        let mut numbers = Vec::new();
+        Searcher::default()
+            .search_path(
+                &CaseInsensitive::new("TO"),
+                "poem.txt",
+                &mut |line: &Line<'_>| {
+                    numbers.push(line.number);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        assert_eq!(vec![2, 6, 8, 9], numbers);
+
+        let err = Searcher::default()
+            .search_path(&Literal::new("to"), "missing.txt", &mut |_: &Line<'_>| {
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(err.to_string().starts_with("missing.txt: "));
+    }
+
+    #[test]
+    fn decodes_inputs() {
+        // UTF-16 with a byte order mark, which would look binary if it weren't decoded
+        let mut contents = vec![0xff, 0xfe];
+        contents.extend(
+            "I'm nobody!\r\nWho are you?\r\n"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+        let mut recorder = Recorder::default();
+        Searcher::default()
+            .search_reader(&Literal::new("Who"), &contents[..], &mut recorder)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "--",
+                "2:[(0, 3)]",
+                "Finish { matched_lines: 1, matches: 1, binary: false }"
+            ],
+            recorder.0
+        );
+
+        let searcher = Searcher {
+            encoding: Some(Encoding::Latin1),
+            ..Searcher::default()
+        };
+        let mut texts = Vec::new();
+        searcher
+            .search_reader(
+                &Literal::new("na\u{ef}ve"),
+                &b"na\xefve"[..],
+                &mut |line: &Line<'_>| {
+                    texts.push(line.text.to_string());
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(vec!["na\u{ef}ve"], texts);
+
+        // A UTF-8 byte order mark isn't part of the first line, also when UTF-8 is asked for
+        let searcher = Searcher {
+            encoding: Some(Encoding::Utf8),
+            ..Searcher::default()
+        };
+        let mut texts = Vec::new();
+        searcher
+            .search_reader(
+                &Literal::new("I'm"),
+                &b"\xef\xbb\xbfI'm nobody!\n"[..],
+                &mut |line: &Line<'_>| {
+                    texts.push(line.text.to_string());
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(vec!["I'm nobody!"], texts);
+    }
+
+    #[test]
+    fn binary_inputs() {
+        let contents: &[u8] = b"one\x00\ntwo\none\n";
+        let mut recorder = Recorder::default();
+        Searcher::default()
+            .search_reader(&Literal::new("one"), contents, &mut recorder)
+            .unwrap();
+
+        assert_eq!(
+            vec!["Finish { matched_lines: 1, matches: 0, binary: true }"],
+            recorder.0
+        );
+
+        let searcher = Searcher {
+            binary: true,
+            ..Searcher::default()
+        };
+        let mut recorder = Recorder::default();
+        searcher
+            .search_reader(&Literal::new("one"), contents, &mut recorder)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "--",
+                "1:[(0, 3)]",
+                "--",
+                "3:[(0, 3)]",
+                "Finish { matched_lines: 2, matches: 2, binary: false }"
+            ],
+            recorder.0
+        );
+    }
+}
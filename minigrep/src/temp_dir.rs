@@ -0,0 +1,31 @@
+use std::fs;
+use std::path::PathBuf;
+
+// A directory for tests to put files in, removed again when it's dropped, also when the test
+// panics. 'name' keeps the directories of tests running at the same time apart
+pub(crate) struct TempDir(pub(crate) PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("minigrep-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub(crate) fn file(&self, relative: &str) {
+        self.write(relative, "contents");
+    }
+
+    pub(crate) fn write(&self, relative: &str, contents: &str) {
+        let path = self.0.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
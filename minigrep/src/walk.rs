@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::glob::Glob;
+use crate::ignore::Ignore;
+
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    // When not empty, only files matching at least one of these globs are yielded
+    pub include: Vec<Glob>,
+    // Files and directories matching any of these globs are skipped entirely
+    pub exclude: Vec<Glob>,
+    pub follow_links: bool,
+    // Don't skip the paths matched by '.gitignore' and '.ignore' files
+    pub no_ignore: bool,
+    // Also walk files and directories whose name starts with a '.'
+    pub hidden: bool,
+}
+
+// Recursively walks a directory, yielding every regular file below it in a stable, sorted order.
+// Symbolic links are skipped unless 'follow_links' is set, in which case directories that were
+// already visited are not entered again so link cycles terminate. Hidden paths and the paths
+// ignored by the ignore files found along the way are skipped, see the 'ignore' module
+pub struct Walk {
+    root: PathBuf,
+    options: WalkOptions,
+    // Paths still to be visited with the ignore rules of their directory, in reverse order so the
+    // next one can be popped off the end
+    pending: Vec<(PathBuf, Option<Arc<Ignore>>)>,
+    visited: HashSet<PathBuf>,
+}
+
+impl Walk {
+    pub fn new(root: &Path, options: WalkOptions) -> Walk {
+        let ignore = match options.no_ignore {
+            true => None,
+            false => Ignore::from_parents(root),
+        };
+
+        Walk {
+            root: root.to_path_buf(),
+            options,
+            pending: vec![(root.to_path_buf(), ignore)],
+            visited: HashSet::new(),
+        }
+    }
+
+    fn is_excluded(&self, relative: &Path) -> bool {
+        self.options
+            .exclude
+            .iter()
+            .any(|glob| glob.is_match(relative))
+    }
+
+    fn is_included(&self, relative: &Path) -> bool {
+        self.options.include.is_empty()
+            || self
+                .options
+                .include
+                .iter()
+                .any(|glob| glob.is_match(relative))
+    }
+
+    fn push_children(&mut self, dir: &Path, ignore: Option<Arc<Ignore>>) -> io::Result<()> {
+        if self.options.follow_links && !self.visited.insert(fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+
+        let ignore = match self.options.no_ignore {
+            true => None,
+            false => {
+                let rules = Ignore::from_dir(dir, ignore.clone());
+                match rules.is_empty() {
+                    true => ignore,
+                    false => Some(Arc::new(rules)),
+                }
+            }
+        };
+
+        let mut children = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()?;
+        children.sort();
+        children.reverse();
+
+        self.pending
+            .extend(children.into_iter().map(|child| (child, ignore.clone())));
+        Ok(())
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<PathBuf, Error>;
+
+    fn next(&mut self) -> Option<Result<PathBuf, Error>> {
+        while let Some((path, ignore)) = self.pending.pop() {
+            let is_root = path == self.root;
+            let relative = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+
+            let hidden = !self.options.hidden && is_hidden(&path);
+            if !is_root && (hidden || self.is_excluded(&relative)) {
+                continue;
+            }
+
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => return Some(Err(path_error(&path, err))),
+            };
+
+            // The root was named explicitly, so it is always followed
+            let metadata = if metadata.file_type().is_symlink() {
+                if !is_root && !self.options.follow_links {
+                    continue;
+                }
+                match fs::metadata(&path) {
+                    Ok(metadata) => metadata,
+                    Err(err) => return Some(Err(path_error(&path, err))),
+                }
+            } else {
+                metadata
+            };
+
+            let is_ignored = |ignore: &Ignore| ignore.is_ignored(&path, metadata.is_dir());
+            if !is_root && ignore.as_deref().is_some_and(is_ignored) {
+                continue;
+            }
+
+            if metadata.is_dir() {
+                if let Err(err) = self.push_children(&path, ignore) {
+                    return Some(Err(path_error(&path, err)));
+                }
+            } else if metadata.is_file() && (is_root || self.is_included(&relative)) {
+                return Some(Ok(path));
+            }
+        }
+
+        None
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn path_error(path: &Path, source: io::Error) -> Error {
+    Error::Io {
+        path: Some(path.display().to_string()),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn walk(dir: &TempDir, options: WalkOptions) -> Vec<String> {
+        Walk::new(&dir.0, options)
+            .map(|path| {
+                let path = path.unwrap();
+                let relative = path.strip_prefix(&dir.0).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    fn globs(globs: &[&str]) -> Vec<Glob> {
+        globs.iter().map(|glob| Glob::new(glob).unwrap()).collect()
+    }
+
+    #[test]
+    fn walks_recursively_in_order() {
+        let dir = TempDir::new("walk-order");
+        dir.file("b.txt");
+        dir.file("a/z.rs");
+        dir.file("a/b/c.rs");
+
+        let result = vec!["a/b/c.rs", "a/z.rs", "b.txt"];
+
+        assert_eq!(result, walk(&dir, WalkOptions::default()));
+    }
+
+    #[test]
+    fn include_and_exclude_globs() {
+        let dir = TempDir::new("walk-globs");
+        dir.file("src/lib.rs");
+        dir.file("src/notes.txt");
+        dir.file("target/debug/build.rs");
+
+        let options = WalkOptions {
+            include: globs(&["*.rs"]),
+            exclude: globs(&["target"]),
+            ..WalkOptions::default()
+        };
+
+        assert_eq!(vec!["src/lib.rs"], walk(&dir, options));
+    }
+
+    #[test]
+    fn ignore_files_and_hidden_paths() {
+        let dir = TempDir::new("walk-ignore");
+        dir.write(".gitignore", "/target\n*.log\n");
+        dir.write("src/.ignore", "!keep.log\ngenerated.rs\n");
+        dir.write(".git/info/exclude", "notes.txt\n");
+        dir.file(".git/HEAD");
+        dir.file(".hidden.rs");
+        dir.file("notes.txt");
+        dir.file("src/lib.rs");
+        dir.file("src/generated.rs");
+        dir.file("src/debug.log");
+        dir.file("src/keep.log");
+        dir.file("target/debug/build.rs");
+
+        assert_eq!(
+            vec!["src/keep.log", "src/lib.rs"],
+            walk(&dir, WalkOptions::default())
+        );
+
+        let options = WalkOptions {
+            no_ignore: true,
+            hidden: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(11, walk(&dir, options).len());
+    }
+
+    #[test]
+    fn ignore_files_above_the_root_of_the_walk() {
+        let dir = TempDir::new("walk-parent-ignore");
+        dir.write(".gitignore", "*.log\n/src/app/generated.rs\n/app\n");
+        dir.write("src/.ignore", "!keep.log\n");
+        dir.write(".git/info/exclude", "notes.txt\n");
+        dir.file("src/app/debug.log");
+        dir.file("src/app/keep.log");
+        dir.file("src/app/generated.rs");
+        dir.file("src/app/notes.txt");
+        dir.file("src/app/main.rs");
+        dir.file("src/app/app/lib.rs");
+
+        let root = dir.0.join("src/app");
+        let walk = |options: WalkOptions| -> Vec<String> {
+            Walk::new(&root, options)
+                .map(|path| {
+                    let path = path.unwrap();
+                    let relative = path.strip_prefix(&root).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+
+        // '/app' is anchored to the top of the repository, not to the root of the walk
+        assert_eq!(
+            vec!["app/lib.rs", "keep.log", "main.rs"],
+            walk(WalkOptions::default())
+        );
+
+        let options = WalkOptions {
+            no_ignore: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(6, walk(options).len());
+
+        // Outside a git repository the directories above the walk are not looked at
+        fs::remove_dir_all(dir.0.join(".git")).unwrap();
+        assert_eq!(6, walk(WalkOptions::default()).len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_not_followed_by_default() {
+        let dir = TempDir::new("walk-links");
+        dir.file("real/file.txt");
+        std::os::unix::fs::symlink(dir.0.join("real"), dir.0.join("link")).unwrap();
+        // A cycle back to the root must not make the walk loop forever
+        std::os::unix::fs::symlink(&dir.0, dir.0.join("real/cycle")).unwrap();
+
+        assert_eq!(vec!["real/file.txt"], walk(&dir, WalkOptions::default()));
+
+        let options = WalkOptions {
+            follow_links: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(vec!["link/file.txt"], walk(&dir, options));
+    }
+}